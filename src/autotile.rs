@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+
+/// Picks a sprite for a tile based on which of its eight neighbours share
+/// its kind, for instance which neighbours of a ground tile are ground too.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AutotileRules {
+    /// The sprite for tiles that no rule matches, if any.
+    pub fallback: Option<usize>,
+    /// Rules in priority order; the first one that matches wins.
+    pub rules: Vec<AutotileRule>,
+}
+
+/// Autotile rules for walls. Walls touching the outside of the level use the
+/// `boundary` rules, and walls standing inside it the `interior` ones, with
+/// `X` in patterns matching a wall of the same kind.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct WallRules {
+    pub boundary: AutotileRules,
+    pub interior: AutotileRules,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AutotileRule {
+    /// Three rows of three tiles, top row first, with the tile itself in the
+    /// middle. `X` matches a tile of the same kind, `.` any other tile, and
+    /// anything else, conventionally `?`, matches both.
+    pub pattern: Vec<String>,
+    pub sprite: usize,
+}
+
+impl AutotileRule {
+    fn matches(&self, neighbours: &[[bool; 3]; 3]) -> bool {
+        self.pattern.iter().zip(neighbours).all(|(pattern, row)| {
+            pattern.chars().zip(row).all(|(c, &same)| match c {
+                'X' => same,
+                '.' => !same,
+                _ => true,
+            })
+        })
+    }
+}
+
+impl AutotileRules {
+    /// The sprite for a tile, given its neighbourhood with the top row first.
+    pub fn sprite(&self, neighbours: &[[bool; 3]; 3]) -> Option<usize> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(neighbours))
+            .map(|rule| rule.sprite)
+            .or(self.fallback)
+    }
+
+    /// Sprites for every tile of a grid stored bottom row first, as levels
+    /// are. Tiles on the edge of the grid get no sprite.
+    pub fn apply(&self, grid: &[Vec<bool>]) -> Vec<Vec<Option<usize>>> {
+        let height = grid.len();
+        let width = grid.first().map_or(0, |row| row.len());
+
+        let mut result = vec![vec![None; width]; height];
+
+        for y in 1..height.saturating_sub(1) {
+            for x in 1..width.saturating_sub(1) {
+                let neighbours = [
+                    [grid[y + 1][x - 1], grid[y + 1][x], grid[y + 1][x + 1]],
+                    [grid[y][x - 1], grid[y][x], grid[y][x + 1]],
+                    [grid[y - 1][x - 1], grid[y - 1][x], grid[y - 1][x + 1]],
+                ];
+
+                result[y][x] = self.sprite(&neighbours);
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outdoor() -> AutotileRules {
+        ron::de::from_str(include_str!("../texture/outdoor_autotile.ron")).unwrap()
+    }
+
+    /// A grid from rows written top down, with `X` for a tile of the kind
+    /// being drawn, stored bottom row first.
+    fn grid(rows: &[&str]) -> Vec<Vec<bool>> {
+        rows.iter()
+            .rev()
+            .map(|row| row.chars().map(|c| c == 'X').collect())
+            .collect()
+    }
+
+    // The expected sprites are those the hard-coded table in `Level` picked
+    // before the rules moved to `outdoor_autotile.ron`.
+
+    #[test]
+    fn corners() {
+        let sprites = outdoor().apply(&grid(&["....", ".XX.", ".XX.", "...."]));
+
+        assert_eq!(sprites[2][1], Some(1));
+        assert_eq!(sprites[2][2], Some(2));
+        assert_eq!(sprites[1][1], Some(3));
+        assert_eq!(sprites[1][2], Some(4));
+    }
+
+    #[test]
+    fn t_junction() {
+        let sprites = outdoor().apply(&grid(&[".....", ".XXX.", "..X..", ".....", "....."]));
+
+        assert_eq!(sprites[3][2], Some(24));
+        assert_eq!(sprites[3][1], Some(23));
+        assert_eq!(sprites[3][3], Some(21));
+        assert_eq!(sprites[2][2], Some(22));
+        // The tile below the stem shows the edge of the ground above it.
+        assert_eq!(sprites[1][2], Some(13));
+    }
+
+    #[test]
+    fn isolated() {
+        let sprites = outdoor().apply(&grid(&[".....", ".....", "..X..", ".....", "....."]));
+
+        assert_eq!(sprites[2][2], Some(9));
+        assert_eq!(sprites[1][2], Some(13));
+        assert_eq!(sprites[3][2], None);
+        // Tiles on the edge of the grid never get a sprite.
+        assert_eq!(sprites[0][2], None);
+    }
+}
@@ -0,0 +1,55 @@
+//! Generates a level that is guaranteed to be solvable and prints it in XSB
+//! format, followed by the solution it was generated with.
+//!
+//! ```text
+//! generate <seed> [width] [height] [boxes] [min pushes]
+//! ```
+
+use std::env;
+use std::process;
+
+use amethyst_sokoban::generator::{generate, Options};
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args.len() > 5 {
+        usage();
+    }
+
+    let numbers: Vec<u64> = args
+        .iter()
+        .map(|arg| arg.parse().unwrap_or_else(|_| usage()))
+        .collect();
+    let defaults = Options::default();
+    let number = |index: usize, default: usize| {
+        numbers
+            .get(index)
+            .map_or(default, |&number| number as usize)
+    };
+
+    let options = Options {
+        seed: numbers[0],
+        width: number(1, defaults.width),
+        height: number(2, defaults.height),
+        boxes: number(3, defaults.boxes),
+        min_pushes: number(4, defaults.min_pushes),
+        ..defaults
+    };
+
+    match generate(&options) {
+        Some(generated) => {
+            print!("{}", generated.to_xsb());
+            println!("Title: Seed {}", options.seed);
+            println!("Pushes: {}", generated.optimal_pushes);
+        }
+        None => {
+            eprintln!("No level found for seed {}", options.seed);
+            process::exit(1);
+        }
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: generate <seed> [width] [height] [boxes] [min pushes]");
+    process::exit(2);
+}
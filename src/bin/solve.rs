@@ -0,0 +1,83 @@
+//! Solves every level of a collection, for comparing saved solutions with
+//! the best possible ones.
+//!
+//! ```text
+//! solve <collection> [pushes|moves]
+//! ```
+//!
+//! Levels are solved for the fewest pushes unless `moves` is given. The
+//! saved solutions next to the collection, if any, are listed alongside.
+
+use std::env;
+use std::process;
+
+use amethyst_sokoban::collection::LevelCollection;
+use amethyst_sokoban::solution::Solutions;
+use amethyst_sokoban::solver::{solve, Limits, Metric, Outcome};
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let metric = match args.get(1).map(|metric| metric.as_str()) {
+        None | Some("pushes") => Metric::Pushes,
+        Some("moves") => Metric::Moves,
+        Some(_) => usage(),
+    };
+    let collection_path = match args.first() {
+        Some(path) => path,
+        None => usage(),
+    };
+
+    let collection = match LevelCollection::load(collection_path) {
+        Ok(collection) => collection,
+        Err(err) => {
+            eprintln!("Failed to load {}: {}", collection_path, err);
+            process::exit(2);
+        }
+    };
+    for err in collection.errors() {
+        eprintln!("Skipped a level of {}: {}", collection_path, err);
+    }
+    let solutions_path = Solutions::path_for(collection_path);
+    let solutions = match Solutions::load(&solutions_path) {
+        Ok(solutions) => solutions,
+        Err(err) => {
+            eprintln!("Failed to load {}: {}", solutions_path.display(), err);
+            process::exit(2);
+        }
+    };
+
+    for (index, entry) in collection.iter().enumerate() {
+        let number = index + 1;
+        let name = match &entry.metadata.title {
+            Some(title) => format!("Level {} ({})", number, title),
+            None => format!("Level {}", number),
+        };
+
+        match solve(&entry.level, metric, &Limits::default()) {
+            Outcome::Solved {
+                lurd,
+                moves,
+                pushes,
+            } => {
+                println!("{}: {} moves and {} pushes", name, moves, pushes);
+                println!("  {}", lurd);
+            }
+            Outcome::Unsolvable => println!("{}: unsolvable", name),
+            Outcome::GaveUp | Outcome::Cancelled => println!("{}: gave up", name),
+        }
+
+        if let Some(solution) = solutions.get(number) {
+            println!(
+                "  saved: {} moves and {} pushes",
+                solution.moves(),
+                solution.pushes()
+            );
+        }
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: solve <collection> [pushes|moves]");
+    process::exit(2);
+}
@@ -0,0 +1,103 @@
+//! Checks the solutions of a level collection.
+//!
+//! ```text
+//! verify <collection> [solutions]
+//! ```
+//!
+//! The solutions default to the `<collection>_solutions.txt` file the game
+//! saves next to the collection. Exits with an error if any solution fails
+//! to solve its level.
+
+use std::env;
+use std::process;
+
+use amethyst_sokoban::collection::LevelCollection;
+use amethyst_sokoban::lurd::verify;
+use amethyst_sokoban::solution::Solutions;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let collection_path = match args.first() {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: verify <collection> [solutions]");
+            process::exit(2);
+        }
+    };
+    let solutions_path = match args.get(1) {
+        Some(path) => path.into(),
+        None => Solutions::path_for(collection_path),
+    };
+
+    let collection = match LevelCollection::load(collection_path) {
+        Ok(collection) => collection,
+        Err(err) => {
+            eprintln!("Failed to load {}: {}", collection_path, err);
+            process::exit(2);
+        }
+    };
+    for err in collection.errors() {
+        eprintln!("Skipped a level of {}: {}", collection_path, err);
+    }
+    let solutions = match Solutions::load(&solutions_path) {
+        Ok(solutions) => solutions,
+        Err(err) => {
+            eprintln!("Failed to load {}: {}", solutions_path.display(), err);
+            process::exit(2);
+        }
+    };
+
+    let mut failures = 0;
+
+    for (index, entry) in collection.iter().enumerate() {
+        let number = index + 1;
+        let name = match &entry.metadata.title {
+            Some(title) => format!("Level {} ({})", number, title),
+            None => format!("Level {}", number),
+        };
+
+        let solution = match solutions.get(number) {
+            Some(solution) => solution,
+            None => {
+                println!("{}: no solution", name);
+                continue;
+            }
+        };
+
+        let verification = verify(&entry.level, &solution.lurd);
+
+        if let Some(illegal) = verification.illegal_step {
+            println!(
+                "{}: step {} is illegal: {}",
+                name,
+                illegal.index + 1,
+                illegal.error
+            );
+            failures += 1;
+        } else if !verification.solved {
+            println!(
+                "{}: {} moves and {} pushes don't solve the level",
+                name, verification.moves, verification.pushes
+            );
+            failures += 1;
+        } else {
+            println!(
+                "{}: solved in {} moves and {} pushes",
+                name, verification.moves, verification.pushes
+            );
+        }
+    }
+
+    for (number, _) in solutions.iter() {
+        if number == 0 || number > collection.len() {
+            println!("Level {}: solution for a level that doesn't exist", number);
+            failures += 1;
+        }
+    }
+
+    if failures > 0 {
+        eprintln!("{} solutions failed", failures);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,583 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Index;
+use std::path::{Path, PathBuf};
+use std::slice;
+
+use xml::common::Position;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::level::{Level, LevelError};
+
+/// The `Key: value` lines attached to a level or to a whole collection.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub comment: Option<String>,
+    /// Every other key, such as `Theme`, as it was written.
+    pub extra: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    /// The value of a key, which is looked up ignoring case.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        match key.to_lowercase().as_str() {
+            "title" => self.title.as_deref(),
+            "author" => self.author.as_deref(),
+            "comment" => self.comment.as_deref(),
+            _ => self
+                .extra
+                .iter()
+                .find(|(extra_key, _)| extra_key.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value.as_str()),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.author.is_none()
+            && self.comment.is_none()
+            && self.extra.is_empty()
+    }
+
+    fn has(&self, key: &str) -> bool {
+        match key.to_lowercase().as_str() {
+            "title" => self.title.is_some(),
+            "author" => self.author.is_some(),
+            "comment" => self.comment.is_some(),
+            _ => self.extra.contains_key(key),
+        }
+    }
+
+    fn set(&mut self, key: &str, value: String) {
+        match key.to_lowercase().as_str() {
+            "title" => self.title = Some(value),
+            "author" => self.author = Some(value),
+            "comment" => self.comment = Some(value),
+            _ => {
+                self.extra.insert(key.to_string(), value);
+            }
+        }
+    }
+
+    fn append_comment(&mut self, line: &str) {
+        match self.comment.as_mut() {
+            Some(comment) if !comment.is_empty() => {
+                comment.push('\n');
+                comment.push_str(line);
+            }
+            _ => self.comment = Some(line.to_string()),
+        }
+    }
+}
+
+pub struct Entry {
+    pub level: Level,
+    pub metadata: Metadata,
+}
+
+#[derive(Debug)]
+pub enum CollectionError {
+    Io(io::Error),
+    Xml(xml::reader::Error),
+    /// The level with the given 1-based number, starting at the given line
+    /// of the file, is invalid.
+    Level {
+        number: usize,
+        line: usize,
+        error: LevelError,
+    },
+}
+
+impl fmt::Display for CollectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CollectionError::Io(err) => write!(f, "{}", err),
+            CollectionError::Xml(err) => write!(f, "{}", err),
+            CollectionError::Level {
+                number,
+                line,
+                error,
+            } => write!(f, "level {} (starting at line {}): {}", number, line, error),
+        }
+    }
+}
+
+impl Error for CollectionError {}
+
+impl From<io::Error> for CollectionError {
+    fn from(err: io::Error) -> CollectionError {
+        CollectionError::Io(err)
+    }
+}
+
+impl From<xml::reader::Error> for CollectionError {
+    fn from(err: xml::reader::Error) -> CollectionError {
+        CollectionError::Xml(err)
+    }
+}
+
+/// A level being read, before its board has been parsed.
+#[derive(Default)]
+struct PendingLevel<'a> {
+    line: usize,
+    board: Vec<&'a str>,
+    board_done: bool,
+    metadata: Metadata,
+    /// Whether the title was taken from a `;` comment, which a `Title:` line
+    /// replaces rather than starting the next level.
+    comment_title: bool,
+}
+
+impl PendingLevel<'_> {
+    /// Whether a `key` line would repeat one the level already has.
+    fn has(&self, key: &str) -> bool {
+        if key.eq_ignore_ascii_case("title") && self.comment_title {
+            return false;
+        }
+
+        self.metadata.has(key)
+    }
+}
+
+/// A set of levels, as found in `.txt`, `.sok` and `.slc` files.
+#[derive(Default)]
+pub struct LevelCollection {
+    pub metadata: Metadata,
+    entries: Vec<Entry>,
+    /// The levels left out because they could not be parsed.
+    errors: Vec<CollectionError>,
+    path: Option<PathBuf>,
+}
+
+impl LevelCollection {
+    /// Loads a collection, reading `.slc` files as XML and anything else as
+    /// plain text.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<LevelCollection, CollectionError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+
+        let mut collection = match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("slc") => {
+                LevelCollection::parse_slc(&source)?
+            }
+            _ => LevelCollection::parse(&source)?,
+        };
+
+        collection.path = Some(path.to_path_buf());
+        Ok(collection)
+    }
+
+    /// The file the collection was loaded from, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Why each level that was left out of the collection could not be
+    /// parsed, in the order they appear in the file.
+    pub fn errors(&self) -> &[CollectionError] {
+        &self.errors
+    }
+
+    /// Parses a collection of XSB levels, separated by blank lines or `;`
+    /// comment lines.
+    ///
+    /// `Key: value` lines after a board belong to that level, while those
+    /// before the first board describe the collection. A `;` comment in front
+    /// of a level is used as its title when it has no `Title:`.
+    ///
+    /// Levels that fail to parse are left out, and listed by `errors`.
+    pub fn parse(source: &str) -> Result<LevelCollection, CollectionError> {
+        let mut collection = LevelCollection::default();
+        let mut pending = PendingLevel::default();
+        let mut in_comment = false;
+
+        for (number, line) in source.lines().enumerate() {
+            let trimmed = line.trim();
+
+            if in_comment {
+                if trimmed.eq_ignore_ascii_case("comment-end:") {
+                    in_comment = false;
+                } else {
+                    metadata_target(&mut collection, &mut pending).append_comment(trimmed);
+                }
+                continue;
+            }
+
+            if is_board_line(line) {
+                if pending.board_done {
+                    collection.push(pending);
+                    pending = PendingLevel::default();
+                }
+
+                if pending.board.is_empty() {
+                    pending.line = number + 1;
+                }
+
+                pending.board.push(line);
+                continue;
+            }
+
+            if !pending.board.is_empty() {
+                pending.board_done = true;
+            }
+
+            if trimmed.is_empty() {
+                continue;
+            }
+
+            if let Some(title) = trimmed.strip_prefix(';') {
+                let title = title.trim();
+
+                if !pending.board.is_empty() {
+                    collection.push(pending);
+                    pending = PendingLevel::default();
+                }
+
+                if !title.is_empty() && pending.metadata.title.is_none() {
+                    pending.metadata.title = Some(title.to_string());
+                    pending.comment_title = true;
+                }
+                continue;
+            }
+
+            match parse_metadata(trimmed) {
+                // A multi-line comment, running until `Comment-End:`.
+                Some((key, "")) if key.eq_ignore_ascii_case("comment") => {
+                    metadata_target(&mut collection, &mut pending).comment = Some(String::new());
+                    in_comment = true;
+                }
+                // A second title after a board starts the next level.
+                Some((key, value)) if !pending.board.is_empty() && pending.has(key) => {
+                    collection.push(pending);
+                    pending = PendingLevel::default();
+                    pending.metadata.set(key, value.to_string());
+                }
+                Some((key, value)) => {
+                    metadata_target(&mut collection, &mut pending).set(key, value.to_string());
+                    if key.eq_ignore_ascii_case("title") {
+                        pending.comment_title = false;
+                    }
+                }
+                None => metadata_target(&mut collection, &mut pending).append_comment(trimmed),
+            }
+        }
+
+        if !pending.board.is_empty() {
+            collection.push(pending);
+        }
+
+        Ok(collection)
+    }
+
+    /// Parses a collection in the SokobanYASC `.slc` XML format.
+    ///
+    /// The `Title`, `Description`, `Email` and `Url` of the file, and the
+    /// `Copyright` of its `LevelCollection`, describe the collection. Each
+    /// `Level` is titled by its `Id`, and its own `Copyright` is its author.
+    ///
+    /// As with `parse`, levels that fail to parse are left out.
+    pub fn parse_slc(source: &str) -> Result<LevelCollection, CollectionError> {
+        let mut collection = LevelCollection::default();
+        let mut pending = PendingLevel::default();
+        let mut rows: Vec<String> = Vec::new();
+        let mut text = String::new();
+        let mut reader = EventReader::from_str(source);
+
+        loop {
+            let line = reader.position().row as usize + 1;
+
+            match reader.next()? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    let attribute = |key: &str| {
+                        attributes
+                            .iter()
+                            .find(|attribute| attribute.name.local_name == key)
+                            .map(|attribute| attribute.value.clone())
+                    };
+
+                    match name.local_name.as_str() {
+                        "LevelCollection" => {
+                            if let Some(copyright) = attribute("Copyright") {
+                                collection
+                                    .metadata
+                                    .extra
+                                    .insert("Copyright".to_string(), copyright);
+                            }
+                        }
+                        "Level" => {
+                            pending = PendingLevel::default();
+                            pending.line = line;
+                            pending.metadata.title = attribute("Id");
+                            pending.metadata.author = attribute("Copyright");
+                            rows.clear();
+                        }
+                        _ => {}
+                    }
+
+                    text.clear();
+                }
+                XmlEvent::Characters(characters) | XmlEvent::Whitespace(characters) => {
+                    text.push_str(&characters)
+                }
+                XmlEvent::EndElement { name } => {
+                    let value = text.trim().to_string();
+
+                    match name.local_name.as_str() {
+                        "L" => rows.push(text.trim_end().to_string()),
+                        "Level" => {
+                            pending.board = rows.iter().map(|row| row.as_str()).collect();
+                            collection.push(pending);
+                            pending = PendingLevel::default();
+                        }
+                        "Title" => collection.metadata.title = Some(value),
+                        "Description" => collection.metadata.comment = Some(value),
+                        "Email" | "Url" => {
+                            collection
+                                .metadata
+                                .extra
+                                .insert(name.local_name.clone(), value);
+                        }
+                        _ => {}
+                    }
+
+                    text.clear();
+                }
+                XmlEvent::EndDocument => break,
+                _ => {}
+            }
+        }
+
+        Ok(collection)
+    }
+
+    /// Adds the level being read, or records why it is left out.
+    fn push(&mut self, pending: PendingLevel<'_>) {
+        match Level::parse(&pending.board.join("\n")) {
+            Ok(level) => self.entries.push(Entry {
+                level,
+                metadata: pending.metadata,
+            }),
+            Err(error) => self.errors.push(CollectionError::Level {
+                number: self.entries.len() + self.errors.len() + 1,
+                line: pending.line,
+                error,
+            }),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Entry> {
+        self.entries.get(index)
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Entry> {
+        self.entries.iter()
+    }
+
+    /// Pairs of indices `(duplicate, original)` for every level that is the
+    /// same puzzle as an earlier one, up to rotation and reflection.
+    pub fn duplicates(&self) -> Vec<(usize, usize)> {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut duplicates = Vec::new();
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            let canonical = entry.level.canonical();
+
+            match seen.get(&canonical) {
+                Some(&original) => duplicates.push((index, original)),
+                None => {
+                    seen.insert(canonical, index);
+                }
+            }
+        }
+
+        duplicates
+    }
+
+    /// Drops every level that duplicates an earlier one, returning the
+    /// `(duplicate, original)` pairs that were removed.
+    pub fn remove_duplicates(&mut self) -> Vec<(usize, usize)> {
+        let duplicates = self.duplicates();
+
+        for &(index, _) in duplicates.iter().rev() {
+            self.entries.remove(index);
+        }
+
+        duplicates
+    }
+}
+
+impl Index<usize> for LevelCollection {
+    type Output = Entry;
+
+    fn index(&self, index: usize) -> &Entry {
+        &self.entries[index]
+    }
+}
+
+impl<'a> IntoIterator for &'a LevelCollection {
+    type Item = &'a Entry;
+    type IntoIter = slice::Iter<'a, Entry>;
+
+    fn into_iter(self) -> slice::Iter<'a, Entry> {
+        self.entries.iter()
+    }
+}
+
+/// Text before the first board describes the collection, everything else
+/// the level being read. Levels left out as invalid still count as boards.
+fn metadata_target<'m>(
+    collection: &'m mut LevelCollection,
+    pending: &'m mut PendingLevel<'_>,
+) -> &'m mut Metadata {
+    if pending.board.is_empty()
+        && collection.entries.is_empty()
+        && collection.errors.is_empty()
+        && pending.metadata.is_empty()
+    {
+        &mut collection.metadata
+    } else {
+        &mut pending.metadata
+    }
+}
+
+/// Whether the line is a row of a board, rather than text around it.
+fn is_board_line(line: &str) -> bool {
+    line.contains('#')
+        && line
+            .chars()
+            .all(|c| " #@+$*.-_|".contains(c) || c.is_ascii_digit())
+}
+
+fn parse_metadata(line: &str) -> Option<(&str, &str)> {
+    let colon = line.find(':')?;
+    let key = line[..colon].trim();
+
+    // Keys are short, like `Title` or `Date Created`, which tells them apart
+    // from prose that happens to contain a colon.
+    if key.is_empty() || key.split_whitespace().count() > 2 {
+        return None;
+    }
+
+    Some((key, line[colon + 1..].trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_invalid_levels() {
+        let source = "#####\n#@$.#\n#####\n\n####\n#@$#\n####\n\n#####\n#.$@#\n#####\n";
+        let collection = LevelCollection::parse(source).unwrap();
+
+        assert_eq!(collection.len(), 2);
+        assert_eq!(collection.errors().len(), 1);
+        assert_eq!(
+            collection.errors()[0].to_string(),
+            "level 2 (starting at line 5): 1 boxes but 0 goals"
+        );
+    }
+
+    #[test]
+    fn title_comments() {
+        let source = "; First\n#####\n#@$.#\n#####\n;Second\n#####\n#.$@#\n#####\n";
+        let collection = LevelCollection::parse(source).unwrap();
+
+        assert_eq!(collection[0].metadata.title.as_deref(), Some("First"));
+        assert_eq!(collection[1].metadata.title.as_deref(), Some("Second"));
+    }
+
+    #[test]
+    fn comment_numbers_with_titles_after_boards() {
+        let source = "; 1\n\n#####\n#@$.#\n#####\nTitle: First\nAuthor: A\n\n\
+                      ; 2\n\n#####\n#.$@#\n#####\nTitle: Second\n";
+        let collection = LevelCollection::parse(source).unwrap();
+
+        assert_eq!(collection.len(), 2);
+        assert_eq!(collection[0].metadata.title.as_deref(), Some("First"));
+        assert_eq!(collection[0].metadata.author.as_deref(), Some("A"));
+        assert_eq!(collection[1].metadata.title.as_deref(), Some("Second"));
+        assert_eq!(collection[1].metadata.author, None);
+    }
+
+    #[test]
+    fn metadata_after_an_invalid_first_level() {
+        let source = "####\n#@$#\n####\n;\nAuthor: B\n#####\n#.$@#\n#####\n";
+        let collection = LevelCollection::parse(source).unwrap();
+
+        assert_eq!(collection.len(), 1);
+        assert_eq!(collection.errors().len(), 1);
+        assert_eq!(collection[0].metadata.author.as_deref(), Some("B"));
+        assert_eq!(collection.metadata.author, None);
+    }
+
+    #[test]
+    fn slc() {
+        let source = r#"<?xml version="1.0" encoding="utf-8"?>
+<SokobanLevels>
+  <Title>Two Rooms</Title>
+  <Description>A pair of small levels.</Description>
+  <Email>someone@example.com</Email>
+  <LevelCollection Copyright="Someone">
+    <Level Id="First" Width="5" Height="3" Copyright="A">
+      <L>#####</L>
+      <L>#@$.#</L>
+      <L>#####</L>
+    </Level>
+    <Level Id="Second" Width="5" Height="3">
+      <L>#####</L>
+      <L>#.$@#</L>
+      <L>#####</L>
+    </Level>
+  </LevelCollection>
+</SokobanLevels>
+"#;
+        let collection = LevelCollection::parse_slc(source).unwrap();
+
+        assert_eq!(collection.metadata.title.as_deref(), Some("Two Rooms"));
+        assert_eq!(
+            collection.metadata.comment.as_deref(),
+            Some("A pair of small levels.")
+        );
+        assert_eq!(collection.metadata.get("Copyright"), Some("Someone"));
+        assert_eq!(
+            collection.metadata.get("Email"),
+            Some("someone@example.com")
+        );
+
+        assert_eq!(collection.len(), 2);
+        assert_eq!(collection[0].level.to_xsb(), "#####\n#@$.#\n#####");
+        assert_eq!(collection[0].metadata.title.as_deref(), Some("First"));
+        assert_eq!(collection[0].metadata.author.as_deref(), Some("A"));
+        assert_eq!(collection[1].level.to_xsb(), "#####\n#.$@#\n#####");
+        assert_eq!(collection[1].metadata.title.as_deref(), Some("Second"));
+        assert_eq!(collection[1].metadata.author, None);
+    }
+
+    #[test]
+    fn slc_malformed() {
+        let source = "<SokobanLevels>\n  <Title>Broken</Level>\n</SokobanLevels>\n";
+
+        match LevelCollection::parse_slc(source) {
+            // Rows count from 0, so this is the mismatched tag on line 2.
+            Err(CollectionError::Xml(error)) => assert_eq!(error.position().row, 1),
+            Err(error) => panic!("expected an XML error, got {}", error),
+            Ok(_) => panic!("expected an XML error"),
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::game::{Direction, GameState};
+use crate::level::Level;
+
+/// Why a position can no longer be solved.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeadlockKind {
+    /// A box was pushed onto one of the level's dead squares.
+    DeadSquare,
+    /// Boxes and walls fill a 2x2 square, with a box off its goal.
+    Block,
+    /// Boxes are stuck against walls and each other, with one off its goal.
+    Freeze,
+    /// Boxes fence off an area the player can't get into, and can't be
+    /// pushed to open it up, while it still has boxes or goals to match.
+    Corral,
+}
+
+impl fmt::Display for DeadlockKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            DeadlockKind::DeadSquare => "the box can't reach any goal from there",
+            DeadlockKind::Block => "boxes are locked together in a square",
+            DeadlockKind::Freeze => "boxes are frozen against the walls and each other",
+            DeadlockKind::Corral => "boxes fence off an area that can't be solved",
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+/// Finds the deadlocks a push may have caused.
+#[derive(Clone, Default)]
+pub struct DeadlockDetector {
+    dead_squares: Vec<Vec<bool>>,
+}
+
+impl DeadlockDetector {
+    pub fn new(level: &Level) -> DeadlockDetector {
+        DeadlockDetector {
+            dead_squares: level.dead_squares(),
+        }
+    }
+
+    fn is_dead(&self, (x, y): (usize, usize)) -> bool {
+        self.dead_squares
+            .get(y)
+            .and_then(|line| line.get(x))
+            .cloned()
+            .unwrap_or(false)
+    }
+
+    /// Checks for a deadlock after the box now at `pushed` was pushed there.
+    pub fn check(&self, game: &GameState, pushed: (usize, usize)) -> Option<DeadlockKind> {
+        if self.is_dead(pushed) {
+            Some(DeadlockKind::DeadSquare)
+        } else if is_block(game, pushed) {
+            Some(DeadlockKind::Block)
+        } else if self.is_freeze(game, pushed) {
+            Some(DeadlockKind::Freeze)
+        } else if self.is_corral(game) {
+            Some(DeadlockKind::Corral)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the box at `pos` can never move again, with at least one of
+    /// the boxes holding it in place off its goal.
+    fn is_freeze(&self, game: &GameState, pos: (usize, usize)) -> bool {
+        let mut frozen = HashSet::new();
+
+        self.is_frozen(game, pos, &mut frozen) && frozen.iter().any(|&pos| !game.is_goal(pos))
+    }
+
+    /// Whether the box at `pos` is blocked both horizontally and vertically.
+    /// Boxes in `frozen` count as walls, which keeps the search from going
+    /// round in circles, and the boxes found frozen are added to it.
+    fn is_frozen(
+        &self,
+        game: &GameState,
+        pos: (usize, usize),
+        frozen: &mut HashSet<(usize, usize)>,
+    ) -> bool {
+        let axes = [
+            (Direction::Left, Direction::Right),
+            (Direction::Down, Direction::Up),
+        ];
+        let mut found = frozen.clone();
+        found.insert(pos);
+
+        for &(a, b) in axes.iter() {
+            let (a, b) = match (a.apply(pos), b.apply(pos)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => continue,
+            };
+
+            let is_wall = |side| game.is_wall(side) || found.contains(&side);
+            if is_wall(a) || is_wall(b) {
+                continue;
+            }
+
+            // Pushing the box either way would put it on a dead square.
+            if self.is_dead(a) && self.is_dead(b) {
+                continue;
+            }
+
+            let held = [a, b]
+                .iter()
+                .any(|&side| game.is_box(side) && self.is_frozen(game, side, &mut found));
+            if !held {
+                return false;
+            }
+        }
+
+        frozen.extend(found);
+        true
+    }
+
+    /// Whether some area the player can't reach is fenced in for good by
+    /// boxes, while it still needs boxes moved in or out of it.
+    fn is_corral(&self, game: &GameState) -> bool {
+        let reachable = flood(game.player(), |pos| game.is_free(pos));
+        let is_reachable = |pos: &(usize, usize)| reachable.contains(pos);
+
+        let mut seen = HashSet::new();
+
+        for &start in game.boxes() {
+            if seen.contains(&start) {
+                continue;
+            }
+
+            // Everything the player can't reach connected to this box: the
+            // corral and the boxes fencing it in.
+            let corral = flood(start, |pos| !game.is_wall(pos) && !is_reachable(&pos));
+            seen.extend(corral.iter().cloned());
+
+            let solved = corral
+                .iter()
+                .all(|&pos| game.is_box(pos) == game.is_goal(pos));
+            if solved {
+                continue;
+            }
+
+            let can_push = corral.iter().filter(|&&pos| game.is_box(pos)).any(|&pos| {
+                Direction::ALL.iter().any(|direction| {
+                    let behind = direction.opposite().apply(pos);
+                    let to = direction.apply(pos);
+
+                    // Boxes of the corral stay put until one of them moves,
+                    // so pushing into them never works.
+                    match (behind, to) {
+                        (Some(behind), Some(to)) if is_reachable(&behind) => {
+                            let blocked = game.is_wall(to)
+                                || self.is_dead(to)
+                                || (game.is_box(to) && corral.contains(&to));
+                            !blocked
+                        }
+                        _ => false,
+                    }
+                })
+            });
+
+            if !can_push {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+/// Whether the box at `pos` completes a 2x2 square of boxes and walls with a
+/// box off its goal.
+fn is_block(game: &GameState, (x, y): (usize, usize)) -> bool {
+    let corners = [
+        (x.checked_sub(1), y.checked_sub(1)),
+        (x.checked_sub(1), Some(y)),
+        (Some(x), y.checked_sub(1)),
+        (Some(x), Some(y)),
+    ];
+
+    corners.iter().any(|corner| {
+        let (left, bottom) = match *corner {
+            (Some(left), Some(bottom)) => (left, bottom),
+            _ => return false,
+        };
+        let square = [
+            (left, bottom),
+            (left + 1, bottom),
+            (left, bottom + 1),
+            (left + 1, bottom + 1),
+        ];
+
+        square
+            .iter()
+            .all(|&pos| game.is_box(pos) || game.is_wall(pos))
+            && square
+                .iter()
+                .any(|&pos| game.is_box(pos) && !game.is_goal(pos))
+    })
+}
+
+/// Every position reachable from `start` through positions that `passable`
+/// accepts, `start` included.
+fn flood<F>(start: (usize, usize), passable: F) -> HashSet<(usize, usize)>
+where
+    F: Fn((usize, usize)) -> bool,
+{
+    let mut reached = HashSet::new();
+    let mut stack = vec![start];
+
+    while let Some(pos) = stack.pop() {
+        if !reached.insert(pos) {
+            continue;
+        }
+
+        for direction in Direction::ALL.iter() {
+            if let Some(next) = direction.apply(pos) {
+                if !reached.contains(&next) && passable(next) {
+                    stack.push(next);
+                }
+            }
+        }
+    }
+
+    reached
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Move;
+
+    /// Pushes the box next to the player in `direction` and checks for a
+    /// deadlock where it ends up.
+    fn check(source: &str, direction: Direction) -> Option<DeadlockKind> {
+        let level = Level::parse(source).unwrap();
+        let detector = DeadlockDetector::new(&level);
+        let mut game = GameState::new(&level);
+
+        match game.try_move(direction) {
+            Move::Push { box_to, .. } => detector.check(&game, box_to),
+            other => panic!("expected a push, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn dead_square() {
+        let source = "######\n#.   #\n#  $@#\n######";
+        assert_eq!(
+            check(source, Direction::Left),
+            Some(DeadlockKind::DeadSquare)
+        );
+    }
+
+    #[test]
+    fn block() {
+        let source = "########\n#      #\n# $$   #\n# $ $@ #\n#....  #\n########";
+        assert_eq!(check(source, Direction::Left), Some(DeadlockKind::Block));
+    }
+
+    #[test]
+    fn freeze() {
+        let source = "#########\n#   $#  #\n#  # $@ #\n#       #\n#  ..   #\n#########";
+        assert_eq!(check(source, Direction::Left), Some(DeadlockKind::Freeze));
+    }
+
+    #[test]
+    fn corral() {
+        let source = "########\n# .#   #\n# $ $@ #\n# .#   #\n########";
+        assert_eq!(check(source, Direction::Left), Some(DeadlockKind::Corral));
+    }
+
+    #[test]
+    fn along_a_wall_with_a_goal() {
+        let source = "######\n#    #\n#. $@#\n######";
+        assert_eq!(check(source, Direction::Left), None);
+    }
+
+    #[test]
+    fn open_corral() {
+        let source = "#########\n#. #    #\n#   $@$ #\n#. #    #\n#########";
+        assert_eq!(check(source, Direction::Left), None);
+    }
+}
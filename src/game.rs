@@ -0,0 +1,359 @@
+use std::collections::HashSet;
+
+use crate::level::Level;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    /// Every direction, with each one next to its opposite.
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    pub fn to_velocity(&self, scale: f32) -> (f32, f32) {
+        match self {
+            Direction::Up => (0.0, scale),
+            Direction::Down => (0.0, -scale),
+            Direction::Left => (-scale, 0.0),
+            Direction::Right => (scale, 0.0),
+        }
+    }
+
+    /// The neighbouring position in this direction, or `None` when it would
+    /// fall off the edge of the grid.
+    pub fn apply(&self, (x, y): (usize, usize)) -> Option<(usize, usize)> {
+        match self {
+            Direction::Up => Some((x, y + 1)),
+            Direction::Down => y.checked_sub(1).map(|y| (x, y)),
+            Direction::Left => x.checked_sub(1).map(|x| (x, y)),
+            Direction::Right => Some((x + 1, y)),
+        }
+    }
+
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    /// First frame of the player's walking animation when facing this way.
+    pub fn sprite_base(&self) -> usize {
+        match self {
+            Direction::Up => 8,
+            Direction::Down => 0,
+            Direction::Left => 12,
+            Direction::Right => 4,
+        }
+    }
+}
+
+/// The result of trying to move the player one step.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Move {
+    /// The player walked onto a free tile.
+    Walk { to: (usize, usize) },
+    /// The player walked onto `to`, pushing the box that stood there onto
+    /// `box_to`.
+    Push {
+        to: (usize, usize),
+        box_to: (usize, usize),
+    },
+    /// A wall, or a box that can't be pushed, is in the way.
+    Blocked,
+}
+
+/// The rules of Sokoban, independent of how the board is rendered.
+#[derive(Clone)]
+pub struct GameState {
+    level: Level,
+    player: (usize, usize),
+    boxes: HashSet<(usize, usize)>,
+    goals: HashSet<(usize, usize)>,
+    moves: usize,
+    pushes: usize,
+}
+
+impl GameState {
+    pub fn new(level: &Level) -> GameState {
+        GameState {
+            level: level.clone(),
+            player: level.player_pos(),
+            boxes: level.boxes_pos().into_iter().collect(),
+            goals: level.goals_pos().into_iter().collect(),
+            moves: 0,
+            pushes: 0,
+        }
+    }
+
+    /// Puts the player and the boxes back where the level starts them, and
+    /// the counters back to zero.
+    pub fn restart(&mut self) {
+        *self = GameState::new(&self.level);
+    }
+
+    /// The level as it stands now, with the player and the boxes where they
+    /// have been moved to.
+    pub fn to_level(&self) -> Level {
+        self.level
+            .with_position(self.player, self.boxes.iter().cloned())
+    }
+
+    pub fn player(&self) -> (usize, usize) {
+        self.player
+    }
+
+    pub fn boxes(&self) -> &HashSet<(usize, usize)> {
+        &self.boxes
+    }
+
+    pub fn goals(&self) -> &HashSet<(usize, usize)> {
+        &self.goals
+    }
+
+    /// Steps taken by the player, pushes included.
+    pub fn moves(&self) -> usize {
+        self.moves
+    }
+
+    pub fn pushes(&self) -> usize {
+        self.pushes
+    }
+
+    pub fn is_box(&self, pos: (usize, usize)) -> bool {
+        self.boxes.contains(&pos)
+    }
+
+    pub fn is_goal(&self, pos: (usize, usize)) -> bool {
+        self.goals.contains(&pos)
+    }
+
+    pub fn is_wall(&self, pos: (usize, usize)) -> bool {
+        self.level.is_wall(pos.0, pos.1)
+    }
+
+    /// Whether a player or box could move onto `pos`.
+    pub fn is_free(&self, pos: (usize, usize)) -> bool {
+        !self.is_wall(pos) && !self.is_box(pos)
+    }
+
+    pub fn try_move(&mut self, direction: Direction) -> Move {
+        let to = match direction.apply(self.player) {
+            Some(to) if !self.level.is_wall(to.0, to.1) => to,
+            _ => return Move::Blocked,
+        };
+
+        if !self.is_box(to) {
+            self.player = to;
+            self.moves += 1;
+            return Move::Walk { to };
+        }
+
+        let box_to = match direction.apply(to) {
+            Some(box_to) if self.is_free(box_to) => box_to,
+            _ => return Move::Blocked,
+        };
+
+        self.boxes.remove(&to);
+        self.boxes.insert(box_to);
+        self.player = to;
+        self.moves += 1;
+        self.pushes += 1;
+
+        Move::Push { to, box_to }
+    }
+
+    /// Takes back a step the player made in `direction`, pulling the box in
+    /// front of them back along if the step was a push.
+    ///
+    /// The result says where the player and the box went, as for `try_move`,
+    /// so a pulled box comes from the tile past `to` in `direction`.
+    pub fn undo(&mut self, direction: Direction, push: bool) -> Move {
+        let from = self.player;
+        let to = match direction.opposite().apply(from) {
+            Some(to) if self.is_free(to) => to,
+            _ => return Move::Blocked,
+        };
+
+        if !push {
+            self.player = to;
+            self.moves = self.moves.saturating_sub(1);
+            return Move::Walk { to };
+        }
+
+        let box_from = match direction.apply(from) {
+            Some(box_from) if self.is_box(box_from) => box_from,
+            _ => return Move::Blocked,
+        };
+
+        self.boxes.remove(&box_from);
+        self.boxes.insert(from);
+        self.player = to;
+        self.moves = self.moves.saturating_sub(1);
+        self.pushes = self.pushes.saturating_sub(1);
+
+        Move::Push { to, box_to: from }
+    }
+
+    pub fn is_solved(&self) -> bool {
+        self.boxes.iter().all(|pos| self.goals.contains(pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(source: &str) -> GameState {
+        GameState::new(&Level::parse(source).unwrap())
+    }
+
+    fn right((x, y): (usize, usize), n: usize) -> (usize, usize) {
+        (x + n, y)
+    }
+
+    #[test]
+    fn walk() {
+        let mut game = game("######\n#@ $.#\n######");
+        let start = game.player();
+
+        assert_eq!(
+            game.try_move(Direction::Right),
+            Move::Walk {
+                to: right(start, 1)
+            }
+        );
+        assert_eq!(game.player(), right(start, 1));
+        assert_eq!((game.moves(), game.pushes()), (1, 0));
+    }
+
+    #[test]
+    fn push() {
+        let mut game = game("######\n#@$ .#\n######");
+        let start = game.player();
+
+        assert_eq!(
+            game.try_move(Direction::Right),
+            Move::Push {
+                to: right(start, 1),
+                box_to: right(start, 2),
+            }
+        );
+        assert_eq!(game.player(), right(start, 1));
+        assert!(game.is_box(right(start, 2)));
+        assert!(!game.is_box(right(start, 1)));
+        assert_eq!((game.moves(), game.pushes()), (1, 1));
+    }
+
+    #[test]
+    fn blocked_by_wall() {
+        let mut game = game("#####\n#@$.#\n#####");
+        let start = game.player();
+
+        assert_eq!(game.try_move(Direction::Left), Move::Blocked);
+        assert_eq!(game.try_move(Direction::Up), Move::Blocked);
+        assert_eq!(game.player(), start);
+        assert_eq!((game.moves(), game.pushes()), (0, 0));
+
+        // The box would be pushed into the wall.
+        game.try_move(Direction::Right);
+        assert_eq!(game.try_move(Direction::Right), Move::Blocked);
+        assert_eq!((game.moves(), game.pushes()), (1, 1));
+    }
+
+    #[test]
+    fn blocked_by_second_box() {
+        let mut game = game("#######\n#@$$..#\n#######");
+        let start = game.player();
+
+        assert_eq!(game.try_move(Direction::Right), Move::Blocked);
+        assert_eq!(game.player(), start);
+        assert!(game.is_box(right(start, 1)));
+        assert!(game.is_box(right(start, 2)));
+        assert_eq!((game.moves(), game.pushes()), (0, 0));
+    }
+
+    #[test]
+    fn undo_walk() {
+        let mut game = game("######\n#@ $.#\n######");
+        let start = game.player();
+
+        game.try_move(Direction::Right);
+        assert_eq!(game.undo(Direction::Right, false), Move::Walk { to: start });
+        assert_eq!(game.player(), start);
+        assert_eq!((game.moves(), game.pushes()), (0, 0));
+    }
+
+    #[test]
+    fn undo_push() {
+        let mut game = game("######\n#@$ .#\n######");
+        let start = game.player();
+
+        game.try_move(Direction::Right);
+        game.try_move(Direction::Right);
+        assert_eq!((game.moves(), game.pushes()), (2, 2));
+
+        assert_eq!(
+            game.undo(Direction::Right, true),
+            Move::Push {
+                to: right(start, 1),
+                box_to: right(start, 2),
+            }
+        );
+        assert_eq!(game.player(), right(start, 1));
+        assert!(game.is_box(right(start, 2)));
+        assert!(!game.is_box(right(start, 3)));
+        assert_eq!((game.moves(), game.pushes()), (1, 1));
+
+        // Undoing a walk where the player has a box behind them leaves it.
+        assert_eq!(game.undo(Direction::Right, false), Move::Walk { to: start });
+        assert!(game.is_box(right(start, 2)));
+        assert_eq!((game.moves(), game.pushes()), (0, 1));
+    }
+
+    #[test]
+    fn solved() {
+        let mut game = game("######\n#@$ .#\n######");
+
+        assert!(!game.is_solved());
+        game.try_move(Direction::Right);
+        assert!(!game.is_solved());
+        game.try_move(Direction::Right);
+        assert!(game.is_solved());
+
+        game.undo(Direction::Right, true);
+        assert!(!game.is_solved());
+    }
+
+    #[test]
+    fn counters() {
+        let mut game = game("#######\n#@ $ .#\n#     #\n#######");
+
+        let directions = [
+            Direction::Right,
+            Direction::Right,
+            Direction::Down,
+            Direction::Up,
+            Direction::Right,
+            Direction::Left,
+        ];
+        for &direction in directions.iter() {
+            assert_ne!(game.try_move(direction), Move::Blocked);
+        }
+        assert_eq!((game.moves(), game.pushes()), (6, 2));
+
+        game.restart();
+        assert_eq!((game.moves(), game.pushes()), (0, 0));
+    }
+}
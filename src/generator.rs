@@ -0,0 +1,488 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::game::Direction;
+use crate::history::Step;
+use crate::level::{Level, MAX_HEIGHT, MAX_WIDTH};
+use crate::lurd::{to_lurd, verify};
+use crate::solver::{solve, Limits, Metric, Outcome};
+
+/// The 3x3 pieces rooms are built from, turned and mirrored at random.
+const TEMPLATES: [[&str; 3]; 12] = [
+    ["   ", "   ", "   "],
+    ["#  ", "   ", "   "],
+    ["## ", "   ", "   "],
+    ["###", "   ", "   "],
+    ["#  ", "#  ", "   "],
+    ["#  ", "#  ", "#  "],
+    ["   ", " # ", "   "],
+    ["#  ", "   ", "  #"],
+    ["## ", "#  ", "   "],
+    ["# #", "   ", "   "],
+    ["## ", "## ", "   "],
+    [" # ", "   ", "   "],
+];
+
+/// What to generate.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Options {
+    /// The same seed and options always give the same level.
+    pub seed: u64,
+    /// The size of the room, inside its outer walls.
+    pub width: usize,
+    pub height: usize,
+    pub boxes: usize,
+    /// The fewest pushes any solution of the level may take.
+    pub min_pushes: usize,
+    /// How many rooms to try before giving up.
+    pub attempts: usize,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            seed: 0,
+            width: 8,
+            height: 8,
+            boxes: 3,
+            min_pushes: 10,
+            attempts: 100,
+        }
+    }
+}
+
+/// A generated level, together with the solution it was generated from.
+pub struct Generated {
+    pub level: Level,
+    /// The reverse of the pulls that moved the boxes off their goals, in
+    /// LURD notation. It solves the level, though not always optimally.
+    pub solution: String,
+    /// The pushes of an optimal solution.
+    pub optimal_pushes: usize,
+}
+
+impl Generated {
+    /// The level in XSB format, with its solution as a `Solution:` line.
+    pub fn to_xsb(&self) -> String {
+        format!(
+            "{}\nSolution: {}\n",
+            self.level.to_xsb().trim_end(),
+            self.solution
+        )
+    }
+}
+
+/// Generates a level that is guaranteed to be solvable: goals are placed in
+/// a room built from templates, then the boxes are pulled away from them,
+/// so that pushing them back solves the level.
+///
+/// Returns `None` when none of the attempts gave a level that takes at
+/// least `min_pushes` pushes.
+pub fn generate(options: &Options) -> Option<Generated> {
+    if options.boxes == 0
+        || options.width < 3
+        || options.height < 3
+        || options.width + 2 > MAX_WIDTH
+        || options.height + 2 > MAX_HEIGHT
+    {
+        return None;
+    }
+
+    let mut rng = Rng::new(options.seed);
+
+    for _ in 0..options.attempts {
+        let mut room = match Room::build(&mut rng, options.width, options.height) {
+            Some(room) => room,
+            None => continue,
+        };
+
+        let steps = match room.pull_boxes(&mut rng, options.boxes) {
+            Some(steps) => steps,
+            None => continue,
+        };
+
+        let level = match Level::parse(&room.to_xsb()) {
+            Ok(level) => level,
+            Err(_) => continue,
+        };
+
+        let solution = to_lurd(&steps);
+        if !verify(&level, &solution).is_valid() {
+            continue;
+        }
+
+        // A node limit rather than a time limit keeps the results the same
+        // from one machine to the next.
+        let limits = Limits {
+            max_nodes: 200_000,
+            max_time: None,
+        };
+
+        match solve(&level, Metric::Pushes, &limits) {
+            Outcome::Solved { pushes, .. } if pushes >= options.min_pushes => {
+                return Some(Generated {
+                    level,
+                    solution,
+                    optimal_pushes: pushes,
+                });
+            }
+            _ => continue,
+        }
+    }
+
+    None
+}
+
+/// A small, seedable random number generator (SplitMix64), so that levels
+/// don't depend on where randomness comes from.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number below `n`, which must not be zero.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// A room being generated, with row 0 at the top as in XSB.
+struct Room {
+    width: usize,
+    height: usize,
+    walls: Vec<Vec<bool>>,
+    goals: Vec<(usize, usize)>,
+    boxes: HashSet<(usize, usize)>,
+    player: (usize, usize),
+}
+
+impl Room {
+    /// Fills a room with templates, surrounded by walls. Returns `None` if
+    /// its floor ended up in more than one piece.
+    fn build(rng: &mut Rng, width: usize, height: usize) -> Option<Room> {
+        let mut walls = vec![vec![true; width + 2]; height + 2];
+
+        for top in (0..height).step_by(3) {
+            for left in (0..width).step_by(3) {
+                let template = TEMPLATES[rng.below(TEMPLATES.len())];
+                let turns = rng.below(4);
+                let mirrored = rng.below(2) == 1;
+
+                for y in 0..3 {
+                    for x in 0..3 {
+                        let (mut tx, mut ty) = if mirrored { (2 - x, y) } else { (x, y) };
+                        for _ in 0..turns {
+                            let turned = (2 - ty, tx);
+                            tx = turned.0;
+                            ty = turned.1;
+                        }
+
+                        if top + y < height && left + x < width {
+                            walls[top + y + 1][left + x + 1] = template[ty].as_bytes()[tx] == b'#';
+                        }
+                    }
+                }
+            }
+        }
+
+        let room = Room {
+            width: width + 2,
+            height: height + 2,
+            walls,
+            goals: Vec::new(),
+            boxes: HashSet::new(),
+            player: (0, 0),
+        };
+
+        let floor = room.floor();
+        let start = *floor.first()?;
+        if room.reach(start).len() != floor.len() {
+            return None;
+        }
+
+        Some(room)
+    }
+
+    fn floor(&self) -> Vec<(usize, usize)> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| !self.walls[y][x])
+            .collect()
+    }
+
+    /// The tile next to `pos` in `direction`, if it is floor.
+    fn step(&self, (x, y): (usize, usize), direction: Direction) -> Option<(usize, usize)> {
+        let (x, y) = match direction {
+            Direction::Up => (x, y.checked_sub(1)?),
+            Direction::Down => (x, y + 1),
+            Direction::Left => (x.checked_sub(1)?, y),
+            Direction::Right => (x + 1, y),
+        };
+
+        if y < self.height && x < self.width && !self.walls[y][x] {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+
+    fn is_free(&self, pos: Option<(usize, usize)>) -> Option<(usize, usize)> {
+        pos.filter(|pos| !self.boxes.contains(pos))
+    }
+
+    /// The tiles the player can walk to from `start`, each with the step
+    /// that first got there.
+    fn reach(&self, start: (usize, usize)) -> Vec<((usize, usize), Option<Direction>)> {
+        let mut reached = vec![(start, None)];
+        let mut seen: HashSet<(usize, usize)> = reached.iter().map(|&(pos, _)| pos).collect();
+        let mut queue: VecDeque<(usize, usize)> = VecDeque::new();
+        queue.push_back(start);
+
+        while let Some(pos) = queue.pop_front() {
+            for &direction in Direction::ALL.iter() {
+                if let Some(next) = self.is_free(self.step(pos, direction)) {
+                    if seen.insert(next) {
+                        reached.push((next, Some(direction)));
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+
+        reached
+    }
+
+    /// The steps walking the player to `end`, which must be reachable.
+    fn walk(&self, end: (usize, usize)) -> Vec<Direction> {
+        let reached = self.reach(self.player);
+        let mut directions = Vec::new();
+        let mut pos = end;
+
+        while pos != self.player {
+            let direction = reached
+                .iter()
+                .find(|&&(reached, _)| reached == pos)
+                .and_then(|&(_, direction)| direction)
+                .expect("walk to an unreachable tile");
+
+            directions.push(direction);
+            pos = self.step(pos, direction.opposite()).unwrap();
+        }
+
+        directions.reverse();
+        directions
+    }
+
+    /// Puts the boxes on goals and pulls them away at random, returning the
+    /// steps that push them back from where the boxes and player end up.
+    fn pull_boxes(&mut self, rng: &mut Rng, count: usize) -> Option<Vec<Step>> {
+        let mut floor = self.floor();
+        if floor.len() < count * 3 {
+            return None;
+        }
+
+        for _ in 0..count {
+            let goal = floor.remove(rng.below(floor.len()));
+            self.goals.push(goal);
+            self.boxes.insert(goal);
+        }
+        self.player = floor[rng.below(floor.len())];
+
+        // The steps of the player while pulling, undone in reverse order
+        // later. The walk to the first pull is left out, as it would only
+        // follow the last push of the solution.
+        let mut pulls: Vec<Step> = Vec::new();
+
+        // Random pulls drift back towards the goals as often as away from
+        // them, so the position with the boxes furthest from the goals is
+        // kept rather than the last one.
+        let mut best = (0, 0, self.boxes.clone(), self.player);
+
+        for _ in 0..count * 60 {
+            let reachable = self.reach(self.player);
+            let options: Vec<((usize, usize), Direction)> = reachable
+                .iter()
+                .flat_map(|&(pos, _)| {
+                    Direction::ALL
+                        .iter()
+                        .map(move |&direction| (pos, direction))
+                })
+                .filter(|&(pos, direction)| {
+                    // The box on the far side of the player follows them.
+                    let has_box = self
+                        .step(pos, direction.opposite())
+                        .filter(|box_pos| self.boxes.contains(box_pos))
+                        .is_some();
+
+                    has_box && self.is_free(self.step(pos, direction)).is_some()
+                })
+                .collect();
+
+            if options.is_empty() {
+                break;
+            }
+
+            // Of two pulls picked at random, the one taking the boxes further
+            // from the goals, which leads to longer solutions without making
+            // every level alike.
+            let (pos, direction) = {
+                let a = options[rng.below(options.len())];
+                let b = options[rng.below(options.len())];
+                if self.distance_after(b) > self.distance_after(a) {
+                    b
+                } else {
+                    a
+                }
+            };
+
+            if !pulls.is_empty() {
+                for walked in self.walk(pos) {
+                    pulls.push(Step {
+                        direction: walked,
+                        push: false,
+                    });
+                }
+            }
+
+            let box_pos = self.step(pos, direction.opposite()).unwrap();
+            self.boxes.remove(&box_pos);
+            self.boxes.insert(pos);
+            self.player = self.step(pos, direction).unwrap();
+            pulls.push(Step {
+                direction,
+                push: true,
+            });
+
+            let distance = self.distance();
+            if distance > best.0 {
+                best = (distance, pulls.len(), self.boxes.clone(), self.player);
+            }
+        }
+
+        let (distance, len, boxes, player) = best;
+        if distance == 0 {
+            return None;
+        }
+
+        pulls.truncate(len);
+        self.boxes = boxes;
+        self.player = player;
+
+        Some(
+            pulls
+                .iter()
+                .rev()
+                .map(|step| Step {
+                    direction: step.direction.opposite(),
+                    push: step.push,
+                })
+                .collect(),
+        )
+    }
+
+    /// How far the boxes are from the goals, each counted from the goal
+    /// nearest to it.
+    fn distance(&self) -> usize {
+        let between = |a: usize, b: usize| a.max(b) - a.min(b);
+
+        self.boxes
+            .iter()
+            .map(|&(x, y)| {
+                self.goals
+                    .iter()
+                    .map(|&(goal_x, goal_y)| between(x, goal_x) + between(y, goal_y))
+                    .min()
+                    .unwrap_or(0)
+            })
+            .sum()
+    }
+
+    /// The `distance` after pulling from `pos` in `direction`.
+    fn distance_after(&mut self, (pos, direction): ((usize, usize), Direction)) -> usize {
+        let box_pos = self.step(pos, direction.opposite()).unwrap();
+        self.boxes.remove(&box_pos);
+        self.boxes.insert(pos);
+        let distance = self.distance();
+        self.boxes.remove(&pos);
+        self.boxes.insert(box_pos);
+
+        distance
+    }
+
+    fn to_xsb(&self) -> String {
+        let goals: HashSet<(usize, usize)> = self.goals.iter().cloned().collect();
+        let mut lines = Vec::new();
+
+        for y in 0..self.height {
+            let line: String = (0..self.width)
+                .map(|x| {
+                    let pos = (x, y);
+                    match (
+                        self.walls[y][x],
+                        pos == self.player,
+                        self.boxes.contains(&pos),
+                        goals.contains(&pos),
+                    ) {
+                        (true, _, _, _) => '#',
+                        (_, true, _, true) => '+',
+                        (_, true, _, false) => '@',
+                        (_, _, true, true) => '*',
+                        (_, _, true, false) => '$',
+                        (_, _, _, true) => '.',
+                        _ => ' ',
+                    }
+                })
+                .collect();
+
+            lines.push(line);
+        }
+
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(seed: u64) -> Options {
+        Options {
+            seed,
+            width: 6,
+            height: 6,
+            boxes: 2,
+            min_pushes: 4,
+            attempts: 50,
+        }
+    }
+
+    #[test]
+    fn same_seed_same_level() {
+        for seed in 0..3 {
+            let first = generate(&options(seed)).unwrap();
+            let second = generate(&options(seed)).unwrap();
+            assert_eq!(first.to_xsb(), second.to_xsb());
+        }
+    }
+
+    #[test]
+    fn solution_solves_the_level() {
+        for seed in 0..3 {
+            let generated = generate(&options(seed)).unwrap();
+            let verification = verify(&generated.level, &generated.solution);
+
+            assert!(verification.is_valid(), "{}", generated.to_xsb());
+            assert!(verification.pushes >= generated.optimal_pushes);
+            assert!(generated.optimal_pushes >= options(seed).min_pushes);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use crate::game::Direction;
+
+/// One step of the player, and whether it pushed a box.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub direction: Direction,
+    pub push: bool,
+}
+
+/// Something the player did that can be undone.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Entry {
+    Step(Step),
+    /// The level was put back as it started. Undoing this replays the steps
+    /// that led up to it.
+    Restart,
+}
+
+/// What the player did in a level, with what was undone kept around until
+/// something new replaces it.
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    entries: Vec<Entry>,
+    /// How many of `entries` are done; the rest can be redone.
+    done: usize,
+}
+
+impl History {
+    /// Adds an entry, forgetting anything undone that came after.
+    pub fn record(&mut self, entry: Entry) {
+        self.entries.truncate(self.done);
+        self.entries.push(entry);
+        self.done += 1;
+    }
+
+    /// The last thing done, which should now be taken back.
+    pub fn undo(&mut self) -> Option<Entry> {
+        if self.done == 0 {
+            return None;
+        }
+
+        self.done -= 1;
+        Some(self.entries[self.done])
+    }
+
+    /// Undoes entries up to and including the last push or restart, or to
+    /// the start of the level if there is none. The entries are returned in
+    /// the order they should be taken back.
+    pub fn undo_push(&mut self) -> Vec<Entry> {
+        let mut undone = Vec::new();
+
+        while let Some(entry) = self.undo() {
+            undone.push(entry);
+
+            match entry {
+                Entry::Step(Step { push: false, .. }) => {}
+                _ => break,
+            }
+        }
+
+        undone
+    }
+
+    /// The next undone entry, which should now be done again.
+    pub fn redo(&mut self) -> Option<Entry> {
+        let entry = *self.entries.get(self.done)?;
+        self.done += 1;
+        Some(entry)
+    }
+
+    /// The steps that lead from the start of the level to where the player
+    /// is now, leaving out undone steps and those before a restart.
+    pub fn steps(&self) -> Vec<Step> {
+        let done = &self.entries[..self.done];
+        let start = done
+            .iter()
+            .rposition(|entry| *entry == Entry::Restart)
+            .map_or(0, |restart| restart + 1);
+
+        done[start..]
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::Step(step) => Some(*step),
+                Entry::Restart => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walk(direction: Direction) -> Entry {
+        Entry::Step(Step {
+            direction,
+            push: false,
+        })
+    }
+
+    fn push(direction: Direction) -> Entry {
+        Entry::Step(Step {
+            direction,
+            push: true,
+        })
+    }
+
+    fn history(entries: &[Entry]) -> History {
+        let mut history = History::default();
+        for &entry in entries {
+            history.record(entry);
+        }
+        history
+    }
+
+    fn step(entry: Entry) -> Step {
+        match entry {
+            Entry::Step(step) => step,
+            Entry::Restart => panic!("expected a step"),
+        }
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut history = history(&[walk(Direction::Up), push(Direction::Left)]);
+
+        assert_eq!(history.undo(), Some(push(Direction::Left)));
+        assert_eq!(history.steps(), vec![step(walk(Direction::Up))]);
+        assert_eq!(history.undo(), Some(walk(Direction::Up)));
+        assert_eq!(history.undo(), None);
+
+        assert_eq!(history.redo(), Some(walk(Direction::Up)));
+        assert_eq!(history.redo(), Some(push(Direction::Left)));
+        assert_eq!(history.redo(), None);
+        assert_eq!(history.steps().len(), 2);
+    }
+
+    #[test]
+    fn record_forgets_redo() {
+        let mut history = history(&[walk(Direction::Up), walk(Direction::Right)]);
+
+        history.undo();
+        history.record(walk(Direction::Down));
+
+        assert_eq!(history.redo(), None);
+        assert_eq!(
+            history.steps(),
+            vec![step(walk(Direction::Up)), step(walk(Direction::Down))]
+        );
+    }
+
+    #[test]
+    fn undo_push() {
+        let mut history = history(&[
+            walk(Direction::Up),
+            push(Direction::Left),
+            walk(Direction::Down),
+            walk(Direction::Down),
+        ]);
+
+        assert_eq!(
+            history.undo_push(),
+            vec![
+                walk(Direction::Down),
+                walk(Direction::Down),
+                push(Direction::Left),
+            ]
+        );
+        assert_eq!(history.steps(), vec![step(walk(Direction::Up))]);
+
+        // Without a push left, it goes back to the start of the level.
+        assert_eq!(history.undo_push(), vec![walk(Direction::Up)]);
+        assert!(history.undo_push().is_empty());
+
+        // Everything undone can be redone in order.
+        assert_eq!(history.redo(), Some(walk(Direction::Up)));
+        assert_eq!(history.redo(), Some(push(Direction::Left)));
+    }
+
+    #[test]
+    fn restart() {
+        let mut history = history(&[walk(Direction::Up), push(Direction::Left), Entry::Restart]);
+        assert!(history.steps().is_empty());
+
+        history.record(walk(Direction::Right));
+        assert_eq!(history.steps(), vec![step(walk(Direction::Right))]);
+
+        // Undoing the restart brings back the steps before it.
+        history.undo();
+        assert_eq!(history.undo(), Some(Entry::Restart));
+        assert_eq!(
+            history.steps(),
+            vec![step(walk(Direction::Up)), step(push(Direction::Left))]
+        );
+
+        // A restart stops undo_push like a push does.
+        history.redo();
+        history.record(walk(Direction::Down));
+        assert_eq!(
+            history.undo_push(),
+            vec![walk(Direction::Down), Entry::Restart]
+        );
+    }
+}
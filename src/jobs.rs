@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use log::{error, info};
+
+use amethyst_sokoban::level::Level;
+use amethyst_sokoban::solver::{solve_watched, Limits, Metric, Outcome, Progress};
+
+/// Tells solver jobs apart, such as when their results come in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct JobId(u64);
+
+/// Sent once a solver job has finished, been cancelled or given up.
+#[derive(Clone, Debug)]
+pub struct SolverDone {
+    pub id: JobId,
+    pub outcome: Outcome,
+}
+
+/// What a job and the thread running it share.
+#[derive(Default)]
+struct Shared {
+    progress: Mutex<Progress>,
+    cancelled: AtomicBool,
+    outcome: Mutex<Option<Outcome>>,
+}
+
+/// Solver jobs running on threads of their own, so that solving never holds
+/// up a frame. `SolverSystem` hands their results to the ECS as
+/// `SolverDone` events.
+#[derive(Default)]
+pub struct SolverJobs {
+    next_id: u64,
+    jobs: BTreeMap<JobId, Arc<Shared>>,
+}
+
+impl SolverJobs {
+    /// Starts solving a level in the background.
+    pub fn start(&mut self, level: Level, metric: Metric, limits: Limits) -> JobId {
+        let id = JobId(self.next_id);
+        self.next_id += 1;
+
+        let shared = Arc::new(Shared::default());
+        let job = Arc::clone(&shared);
+
+        let spawned = thread::Builder::new()
+            .name(format!("solver {}", id.0))
+            .spawn(move || {
+                let outcome = solve_watched(&level, metric, &limits, |progress| {
+                    *job.progress.lock().unwrap() = *progress;
+                    !job.cancelled.load(Ordering::Relaxed)
+                });
+
+                *job.outcome.lock().unwrap() = Some(outcome);
+            });
+
+        if let Err(err) = spawned {
+            error!("Failed to start a solver thread: {}", err);
+            *shared.outcome.lock().unwrap() = Some(Outcome::GaveUp);
+        }
+
+        info!("Started solver job {}", id.0);
+        self.jobs.insert(id, shared);
+
+        id
+    }
+
+    /// Asks a job to stop. It still reports back, as `Outcome::Cancelled`
+    /// unless it finished first.
+    pub fn cancel(&self, id: JobId) {
+        if let Some(job) = self.jobs.get(&id) {
+            job.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Asks every job to stop, like `cancel`.
+    pub fn cancel_all(&self) {
+        for job in self.jobs.values() {
+            job.cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// How far along a job is, if it is still running.
+    pub fn progress(&self, id: JobId) -> Option<Progress> {
+        self.jobs.get(&id).map(|job| *job.progress.lock().unwrap())
+    }
+
+    /// The jobs still running, oldest first.
+    pub fn running(&self) -> impl Iterator<Item = JobId> + '_ {
+        self.jobs.keys().cloned()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Removes the jobs that have finished, returning their results.
+    pub fn take_finished(&mut self) -> Vec<SolverDone> {
+        let finished: Vec<SolverDone> = self
+            .jobs
+            .iter()
+            .filter_map(|(&id, job)| {
+                job.outcome
+                    .lock()
+                    .unwrap()
+                    .take()
+                    .map(|outcome| SolverDone { id, outcome })
+            })
+            .collect();
+
+        for done in &finished {
+            self.jobs.remove(&done.id);
+        }
+
+        finished
+    }
+}
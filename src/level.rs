@@ -1,3 +1,8 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::autotile::{AutotileRules, WallRules};
+
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum Tile {
     Empty,
@@ -6,54 +11,230 @@ pub enum Tile {
     Box,
     Goal,
     BoxInGoal,
+    PlayerInGoal,
 }
 
+#[derive(Clone)]
 pub struct Level {
-    tiles: [[Tile; 20]; 16],
+    width: usize,
+    height: usize,
+    tiles: Vec<Vec<Tile>>,
+}
+
+/// Levels smaller than this are padded, so that small puzzles keep a sensible
+/// scale on screen.
+pub const MIN_WIDTH: usize = 20;
+pub const MIN_HEIGHT: usize = 16;
+
+/// The largest level, in tiles, that `Level::parse` accepts.
+pub const MAX_WIDTH: usize = 100;
+pub const MAX_HEIGHT: usize = 100;
+
+/// Why a level could not be parsed.
+///
+/// Lines and columns start at 1, and refer to the level after run-length
+/// encoding has been expanded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LevelError {
+    NoPlayer,
+    MultiplePlayers {
+        line: usize,
+        column: usize,
+    },
+    BoxGoalMismatch {
+        boxes: usize,
+        goals: usize,
+    },
+    UnknownCharacter {
+        character: char,
+        line: usize,
+        column: usize,
+    },
+    NotEnclosed {
+        line: usize,
+        column: usize,
+    },
+    TooLarge {
+        width: usize,
+        height: usize,
+    },
+    TrailingCount {
+        line: usize,
+        column: usize,
+    },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::NoPlayer => write!(f, "no player on map"),
+            LevelError::MultiplePlayers { line, column } => write!(
+                f,
+                "line {}, column {}: more than one player on map",
+                line, column
+            ),
+            LevelError::BoxGoalMismatch { boxes, goals } => {
+                write!(f, "{} boxes but {} goals", boxes, goals)
+            }
+            LevelError::UnknownCharacter {
+                character,
+                line,
+                column,
+            } => write!(
+                f,
+                "line {}, column {}: unknown character {:?}",
+                line, column, character
+            ),
+            LevelError::NotEnclosed { line, column } => write!(
+                f,
+                "line {}, column {}: player can walk off the map",
+                line, column
+            ),
+            LevelError::TooLarge { width, height } => write!(
+                f,
+                "level is {}x{}, larger than the maximum of {}x{}",
+                width, height, MAX_WIDTH, MAX_HEIGHT
+            ),
+            LevelError::TrailingCount { line, column } => write!(
+                f,
+                "line {}, column {}: count with nothing to repeat",
+                line, column
+            ),
+        }
+    }
 }
 
+impl Error for LevelError {}
+
 impl Level {
-    pub fn parse(source: &str) -> Level {
-        let mut tiles = [[Tile::Empty; 20]; 16];
-
-        let width = source.lines().map(|x| x.len()).max().unwrap();
-        let height = source.trim_end().lines().count();
-        println!("{}x{}", width, height);
-        let offset = ((20 - width) / 2, (16 - height) / 2);
-
-        let mut x = offset.0;
-        let mut y = offset.1;
-
-        for c in source.chars() {
-            match c {
-                ' ' => tiles[16 - y][x] = Tile::Empty,
-                '#' => tiles[16 - y][x] = Tile::Wall,
-                '.' => tiles[16 - y][x] = Tile::Goal,
-                '$' => tiles[16 - y][x] = Tile::Box,
-                '@' => tiles[16 - y][x] = Tile::Player,
-                '+' => tiles[16 - y][x] = Tile::BoxInGoal,
-                _ => {}
-            };
+    /// Parses a level in the XSB format, including run-length encoded rows
+    /// such as `3#2-$` and rows separated by `|`.
+    pub fn parse(source: &str) -> Result<Level, LevelError> {
+        let rows = expand_rows(source)?;
 
-            if c == '\n' {
-                x = offset.0;
-                y += 1;
-            } else {
-                x += 1;
+        let width = rows.iter().map(|x| x.chars().count()).max().unwrap_or(0);
+        let height = rows.len();
+
+        if width > MAX_WIDTH || height > MAX_HEIGHT {
+            return Err(LevelError::TooLarge { width, height });
+        }
+
+        // Keep at least one empty tile around the level, so that every tile
+        // of the level has a full set of neighbours.
+        let grid_width = MIN_WIDTH.max(width + 2);
+        let grid_height = MIN_HEIGHT.max(height + 2);
+        let offset = ((grid_width - width) / 2, (grid_height - height) / 2);
+
+        // Converts between grid positions and 1-based lines and columns.
+        let to_grid =
+            |line: usize, column: usize| (offset.0 + column - 1, grid_height - offset.1 - line);
+        let to_source = |(x, y): (usize, usize)| (grid_height - offset.1 - y, x + 1 - offset.0);
+
+        let mut tiles = vec![vec![Tile::Empty; grid_width]; grid_height];
+        let mut player = None;
+        let mut boxes = 0;
+        let mut goals = 0;
+
+        for (row, line) in rows.iter().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                let (x, y) = to_grid(row + 1, column + 1);
+
+                tiles[y][x] = match c {
+                    ' ' | '-' | '_' => Tile::Empty,
+                    '#' => Tile::Wall,
+                    '.' => Tile::Goal,
+                    '$' => Tile::Box,
+                    '*' => Tile::BoxInGoal,
+                    '@' => Tile::Player,
+                    '+' => Tile::PlayerInGoal,
+                    character => {
+                        return Err(LevelError::UnknownCharacter {
+                            character,
+                            line: row + 1,
+                            column: column + 1,
+                        })
+                    }
+                };
+
+                if c == '@' || c == '+' {
+                    if player.is_some() {
+                        return Err(LevelError::MultiplePlayers {
+                            line: row + 1,
+                            column: column + 1,
+                        });
+                    }
+
+                    player = Some((x, y));
+                }
+
+                if c == '$' || c == '*' {
+                    boxes += 1;
+                }
+
+                if c == '.' || c == '*' || c == '+' {
+                    goals += 1;
+                }
             }
         }
 
-        Level { tiles }
+        let player = player.ok_or(LevelError::NoPlayer)?;
+
+        if boxes != goals {
+            return Err(LevelError::BoxGoalMismatch { boxes, goals });
+        }
+
+        // Every tile the player can reach must be inside the level, which
+        // always leaves the padding around it unreachable.
+        let mut visited = vec![vec![false; grid_width]; grid_height];
+        let mut stack = vec![player];
+
+        while let Some((x, y)) = stack.pop() {
+            if tiles[y][x] == Tile::Wall || visited[y][x] {
+                continue;
+            }
+
+            visited[y][x] = true;
+
+            let (line, column) = to_source((x, y));
+
+            if line == 1 || line == height || column == 1 || column >= rows[line - 1].len() {
+                return Err(LevelError::NotEnclosed { line, column });
+            }
+
+            stack.extend(&[(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]);
+        }
+
+        Ok(Level {
+            width: grid_width,
+            height: grid_height,
+            tiles,
+        })
+    }
+
+    /// The level in XSB format, without the padding added by `parse`.
+    pub fn to_xsb(&self) -> String {
+        self.to_string()
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
     }
 
     pub fn is_wall(&self, x: usize, y: usize) -> bool {
-        self.tiles[y][x] == Tile::Wall
+        match self.tiles.get(y).and_then(|line| line.get(x)) {
+            Some(&tile) => tile == Tile::Wall,
+            None => true,
+        }
     }
 
     pub fn player_pos(&self) -> (usize, usize) {
         for (y, line) in self.tiles.iter().enumerate() {
             for (x, tile) in line.iter().enumerate() {
-                if *tile == Tile::Player {
+                if *tile == Tile::Player || *tile == Tile::PlayerInGoal {
                     return (x, y);
                 }
             }
@@ -84,7 +265,7 @@ impl Level {
             .enumerate()
             .flat_map(|(y, line)| {
                 line.iter().enumerate().filter_map(move |(x, &tile)| {
-                    if tile == Tile::Goal || tile == Tile::BoxInGoal {
+                    if tile == Tile::Goal || tile == Tile::BoxInGoal || tile == Tile::PlayerInGoal {
                         Some((x, y))
                     } else {
                         None
@@ -94,99 +275,689 @@ impl Level {
             .collect()
     }
 
-    pub fn ground(&self) -> [[usize; 20]; 16] {
-        let mut ground = [[false; 20]; 16];
-        let mut stack = vec![self.player_pos()];
+    /// The same level with the player and the boxes moved to the given
+    /// positions, such as to solve it from partway through.
+    pub fn with_position<I>(&self, player: (usize, usize), boxes: I) -> Level
+    where
+        I: IntoIterator<Item = (usize, usize)>,
+    {
+        let mut level = self.clone();
+
+        for tile in level.tiles.iter_mut().flat_map(|line| line.iter_mut()) {
+            *tile = match *tile {
+                Tile::Player | Tile::Box => Tile::Empty,
+                Tile::PlayerInGoal | Tile::BoxInGoal => Tile::Goal,
+                tile => tile,
+            };
+        }
+
+        for (x, y) in boxes {
+            let tile = &mut level.tiles[y][x];
+            *tile = if *tile == Tile::Goal {
+                Tile::BoxInGoal
+            } else {
+                Tile::Box
+            };
+        }
+
+        let tile = &mut level.tiles[player.1][player.0];
+        *tile = if *tile == Tile::Goal {
+            Tile::PlayerInGoal
+        } else {
+            Tile::Player
+        };
+
+        level
+    }
+
+    /// Every tile reachable from `start` through tiles that `passable`
+    /// accepts.
+    fn flood<F>(&self, start: (usize, usize), passable: F) -> Vec<Vec<bool>>
+    where
+        F: Fn(Tile) -> bool,
+    {
+        let mut reached = vec![vec![false; self.width]; self.height];
+        let mut stack = vec![start];
 
         while let Some(pos) = stack.pop() {
-            if self.tiles[pos.1][pos.0] == Tile::Wall {
+            if !passable(self.tiles[pos.1][pos.0]) {
                 continue;
             }
 
-            if ground[pos.1][pos.0] {
+            if reached[pos.1][pos.0] {
                 continue;
             }
 
-            ground[pos.1][pos.0] = true;
+            reached[pos.1][pos.0] = true;
 
             if pos.0 > 0 {
                 stack.push((pos.0 - 1, pos.1))
             }
-            if pos.0 < 20 {
+            if pos.0 + 1 < self.width {
                 stack.push((pos.0 + 1, pos.1))
             }
             if pos.1 > 0 {
                 stack.push((pos.0, pos.1 - 1))
             }
-            if pos.1 < 16 {
+            if pos.1 + 1 < self.height {
                 stack.push((pos.0, pos.1 + 1))
             }
         }
 
-        let mut result = [[0usize; 20]; 16];
-
-        for y in 1..15 {
-            for x in 1..19 {
-                result[y][x] = match (
-                    ground[y - 1][x - 1],
-                    ground[y - 1][x],
-                    ground[y - 1][x + 1],
-                    ground[y][x - 1],
-                    ground[y][x],
-                    ground[y][x + 1],
-                    ground[y + 1][x - 1],
-                    ground[y + 1][x],
-                    ground[y + 1][x + 1],
-                ) {
-                    // Corners
-                    (_, true, true, false, true, true, _, false, _) => 1, // Large ┏
-                    (_, true, false, false, true, true, _, false, _) => 16, // Tight ┏
-                    (true, true, _, true, true, false, _, false, _) => 2, // Large ┓
-                    (false, true, _, true, true, false, _, false, _) => 17, // Tight ┓
-                    (_, false, _, false, true, true, _, true, true) => 3, // Large ┗
-                    (_, false, _, false, true, true, _, true, false) => 18, // Tight ┗
-                    (_, false, _, true, true, false, true, true, _) => 4, // Large ┛
-                    (_, false, _, true, true, false, false, true, _) => 19, // Tight ┛
-                    // Large borders
-                    (_, true, true, false, true, true, _, true, true) => 5,
-                    (true, true, _, true, true, false, true, true, _) => 6,
-                    (true, true, true, true, true, true, _, false, _) => 7,
-                    (_, false, _, true, true, true, true, true, true) => 8,
-                    // Small corridors
-                    (_, false, _, true, true, true, _, false, _) => 14,
-                    (_, true, _, false, true, false, _, true, _) => 15,
-                    // Dead ends
-                    (_, true, _, false, true, false, _, false, _) => 20,
-                    (_, false, _, true, true, false, _, false, _) => 21,
-                    (_, false, _, false, true, false, _, true, _) => 22,
-                    (_, false, _, false, true, true, _, false, _) => 23,
-                    // Three-ways
-                    (false, true, false, true, true, true, _, false, _) => 24,
-                    (false, true, _, true, true, false, false, true, _) => 25,
-                    (_, true, false, false, true, true, _, true, false) => 26,
-                    (_, false, _, true, true, true, false, true, false) => 27,
-                    // Tight cross
-                    (false, true, false, true, true, true, false, true, false) => 28,
-                    // Foobar
-                    (false, true, true, true, true, true, _, false, false) => 29,
-                    (true, true, false, true, true, true, false, false, _) => 30,
-                    // // Foobar
-                    // (_, true, true, false, true, true, _, true, true) => 5,
-                    // (true, true, _, true, true, false, true, true, _) => 6,
-                    // (true, true, true, true, true, true, _, false, _) => 7,
-                    // (_, false, _, true, true, true, true, true, true) => 8,
-                    // Floaters
-                    (_, _, _, _, false, _, false, true, true) => 10,
-                    (_, _, _, _, false, _, true, true, false) => 11,
-                    (_, _, _, _, false, _, true, true, true) => 12,
-                    (_, _, _, _, false, _, false, true, false) => 13,
-                    // Catch-all
-                    (_, _, _, _, true, _, _, _, _) => 9,
-                    (_, _, _, _, _, _, _, _, _) => 0,
+        reached
+    }
+
+    /// The floor of the level: every tile the player could walk on if there
+    /// were no boxes.
+    fn floor(&self) -> Vec<Vec<bool>> {
+        self.flood(self.player_pos(), |tile| tile != Tile::Wall)
+    }
+
+    /// The sprite to draw for each tile of ground, according to `rules`.
+    pub fn ground(&self, rules: &AutotileRules) -> Vec<Vec<Option<usize>>> {
+        rules.apply(&self.floor())
+    }
+
+    /// The floor tiles from which a box can never be pushed onto any goal,
+    /// however the other boxes stand.
+    ///
+    /// Found by pulling a box away from each goal: a box can be pulled onto
+    /// a tile when the player has room to step back past it, and any floor
+    /// no pull reaches is dead.
+    pub fn dead_squares(&self) -> Vec<Vec<bool>> {
+        let floor = self.floor();
+        let is_floor = |(x, y): (usize, usize)| {
+            floor.get(y).and_then(|line| line.get(x)).cloned() == Some(true)
+        };
+
+        let mut live = vec![vec![false; self.width]; self.height];
+        let mut stack = self.goals_pos();
+
+        while let Some(pos) = stack.pop() {
+            if live[pos.1][pos.0] {
+                continue;
+            }
+            live[pos.1][pos.0] = true;
+
+            let neighbours = [
+                (pos.0.checked_sub(1), Some(pos.1)),
+                (Some(pos.0 + 1), Some(pos.1)),
+                (Some(pos.0), pos.1.checked_sub(1)),
+                (Some(pos.0), Some(pos.1 + 1)),
+            ];
+
+            for &neighbour in neighbours.iter() {
+                let (x, y) = match neighbour {
+                    (Some(x), Some(y)) => (x, y),
+                    _ => continue,
+                };
+
+                // The player steps back from `(x, y)` onto the tile beyond.
+                let beyond = ((x + x).checked_sub(pos.0), (y + y).checked_sub(pos.1));
+
+                if let (Some(bx), Some(by)) = beyond {
+                    if is_floor((x, y)) && is_floor((bx, by)) {
+                        stack.push((x, y));
+                    }
                 }
             }
         }
 
-        result
+        floor
+            .iter()
+            .zip(live.iter())
+            .map(|(floor, live)| {
+                floor
+                    .iter()
+                    .zip(live.iter())
+                    .map(|(&floor, &live)| floor && !live)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The sprite to draw for each wall, according to `rules`.
+    pub fn walls(&self, rules: &WallRules) -> Vec<Vec<Option<usize>>> {
+        let floor = self.floor();
+        let walls: Vec<Vec<bool>> = self
+            .tiles
+            .iter()
+            .map(|line| line.iter().map(|&tile| tile == Tile::Wall).collect())
+            .collect();
+
+        // A wall is on the boundary when any of its neighbours is neither
+        // floor nor wall, that is, outside of the level.
+        let on_boundary: Vec<Vec<bool>> = (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| {
+                        walls[y][x]
+                            && (y.saturating_sub(1)..=(y + 1).min(self.height - 1)).any(|ny| {
+                                (x.saturating_sub(1)..=(x + 1).min(self.width - 1))
+                                    .any(|nx| !floor[ny][nx] && !walls[ny][nx])
+                            })
+                    })
+                    .collect()
+            })
+            .collect();
+        let inside: Vec<Vec<bool>> = walls
+            .iter()
+            .zip(&on_boundary)
+            .map(|(walls, on_boundary)| {
+                walls
+                    .iter()
+                    .zip(on_boundary)
+                    .map(|(&wall, &on_boundary)| wall && !on_boundary)
+                    .collect()
+            })
+            .collect();
+
+        // Each kind of wall only joins up with walls of the same kind.
+        let boundary = rules.boundary.apply(&on_boundary);
+        let interior = rules.interior.apply(&inside);
+
+        (0..self.height)
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| {
+                        if on_boundary[y][x] {
+                            boundary[y][x]
+                        } else if inside[y][x] {
+                            interior[y][x]
+                        } else {
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// A textual form of the level that is the same for every copy of the
+    /// puzzle, whatever its position, rotation or reflection, the decoration
+    /// outside its walls, or where in its area the player starts.
+    pub fn canonical(&self) -> String {
+        let floor = self.floor();
+        let player_area = self.flood(self.player_pos(), |tile| {
+            tile != Tile::Wall && tile != Tile::Box && tile != Tile::BoxInGoal
+        });
+
+        let near_floor = |x: usize, y: usize| {
+            (y.saturating_sub(1)..=(y + 1).min(self.height - 1)).any(|ny| {
+                (x.saturating_sub(1)..=(x + 1).min(self.width - 1)).any(|nx| floor[ny][nx])
+            })
+        };
+
+        // Only the floor and the walls right around it matter, and the player
+        // is added back once the level has been oriented.
+        let mut cells = Vec::new();
+
+        for (y, line) in self.tiles.iter().enumerate() {
+            for (x, &tile) in line.iter().enumerate() {
+                let c = match tile {
+                    Tile::Wall if near_floor(x, y) => '#',
+                    _ if !floor[y][x] => continue,
+                    Tile::Box => '$',
+                    Tile::BoxInGoal => '*',
+                    Tile::Goal | Tile::PlayerInGoal => '.',
+                    _ => ' ',
+                };
+
+                cells.push((x, y, c));
+            }
+        }
+
+        let min_x = cells.iter().map(|&(x, _, _)| x).min().unwrap_or(0);
+        let min_y = cells.iter().map(|&(_, y, _)| y).min().unwrap_or(0);
+        let width = cells
+            .iter()
+            .map(|&(x, _, _)| x + 1 - min_x)
+            .max()
+            .unwrap_or(0);
+        let height = cells
+            .iter()
+            .map(|&(_, y, _)| y + 1 - min_y)
+            .max()
+            .unwrap_or(0);
+
+        let player_cells: Vec<(usize, usize)> = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| player_area[y][x])
+            .map(|(x, y)| (x - min_x, y - min_y))
+            .collect();
+
+        (0..8)
+            .map(|symmetry| {
+                let transform = |(x, y): (usize, usize)| match symmetry {
+                    0 => (x, y),
+                    1 => (width - 1 - x, y),
+                    2 => (x, height - 1 - y),
+                    3 => (width - 1 - x, height - 1 - y),
+                    4 => (y, x),
+                    5 => (height - 1 - y, x),
+                    6 => (y, width - 1 - x),
+                    _ => (height - 1 - y, width - 1 - x),
+                };
+                let (grid_width, grid_height) = if symmetry < 4 {
+                    (width, height)
+                } else {
+                    (height, width)
+                };
+
+                let mut grid = vec![vec![' '; grid_width]; grid_height];
+
+                for &(x, y, c) in &cells {
+                    let (x, y) = transform((x - min_x, y - min_y));
+                    grid[y][x] = c;
+                }
+
+                let player = player_cells
+                    .iter()
+                    .map(|&pos| transform(pos))
+                    .min_by_key(|&(x, y)| (y, x));
+
+                if let Some((x, y)) = player {
+                    grid[y][x] = if grid[y][x] == '.' { '+' } else { '@' };
+                }
+
+                grid.iter()
+                    .map(|row| row.iter().collect::<String>().trim_end().to_string())
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            })
+            .min()
+            .unwrap()
+    }
+
+    /// A hash of `canonical`, which stays the same between runs and builds.
+    pub fn canonical_hash(&self) -> u64 {
+        // 64-bit FNV-1a.
+        self.canonical()
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let used = |x: usize, y: usize| self.tiles[y][x] != Tile::Empty;
+
+        let columns: Vec<usize> = (0..self.width)
+            .filter(|&x| (0..self.height).any(|y| used(x, y)))
+            .collect();
+        let rows: Vec<usize> = (0..self.height)
+            .filter(|&y| (0..self.width).any(|x| used(x, y)))
+            .collect();
+
+        let (first_column, last_column) = match (columns.first(), columns.last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return Ok(()),
+        };
+
+        // Rows are stored bottom up, but written top down.
+        for (i, y) in (rows[0]..=rows[rows.len() - 1]).rev().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            let line: String = (first_column..=last_column)
+                .map(|x| match self.tiles[y][x] {
+                    Tile::Empty => ' ',
+                    Tile::Wall => '#',
+                    Tile::Player => '@',
+                    Tile::Box => '$',
+                    Tile::Goal => '.',
+                    Tile::BoxInGoal => '*',
+                    Tile::PlayerInGoal => '+',
+                })
+                .collect();
+
+            write!(f, "{}", line.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits the source into rows, expanding run-length encoding, so that every
+/// character of a row is exactly one tile.
+///
+/// Rows wider than `MAX_WIDTH` are not expanded, so that a huge count can't
+/// use up all memory before the level is turned down.
+fn expand_rows(source: &str) -> Result<Vec<String>, LevelError> {
+    let mut too_wide: Option<usize> = None;
+    let mut rows = Vec::new();
+
+    for (index, line) in source.lines().flat_map(|line| line.split('|')).enumerate() {
+        let mut row = String::new();
+        let mut width = 0usize;
+        // Whitespace only counts towards the width once something follows
+        // it, as it is trimmed from the end of the row.
+        let mut blank: Vec<(char, usize)> = Vec::new();
+        let mut count: Option<usize> = None;
+
+        for c in line.chars() {
+            if let Some(digit) = c.to_digit(10) {
+                count = Some(
+                    count
+                        .unwrap_or(0)
+                        .saturating_mul(10)
+                        .saturating_add(digit as usize),
+                );
+                continue;
+            }
+
+            let count = count.take().unwrap_or(1);
+            if c.is_whitespace() {
+                blank.push((c, count));
+                continue;
+            }
+
+            for (c, count) in blank.drain(..).chain(Some((c, count))) {
+                width = width.saturating_add(count);
+                if width <= MAX_WIDTH {
+                    for _ in 0..count {
+                        row.push(c);
+                    }
+                }
+            }
+        }
+
+        if count.is_some() {
+            let column = blank
+                .iter()
+                .fold(width, |width, &(_, count)| width.saturating_add(count));
+
+            return Err(LevelError::TrailingCount {
+                line: index + 1,
+                column: column.saturating_add(1),
+            });
+        }
+
+        if width > MAX_WIDTH {
+            too_wide = Some(too_wide.unwrap_or(0).max(width));
+        }
+
+        rows.push(row);
+    }
+
+    let used = rows
+        .iter()
+        .rposition(|row| !row.is_empty())
+        .map_or(0, |last| last + 1);
+    rows.truncate(used);
+
+    match too_wide {
+        Some(width) => Err(LevelError::TooLarge {
+            width,
+            height: rows.len(),
+        }),
+        None => Ok(rows),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `source`, writes it back out and parses that again, checking
+    /// that both come out as `expected`.
+    fn round_trip(source: &str, expected: &str) {
+        let level = Level::parse(source).unwrap();
+        assert_eq!(level.to_xsb(), expected);
+
+        let again = Level::parse(&level.to_xsb()).unwrap();
+        assert_eq!(again.to_xsb(), expected);
+        assert_eq!(
+            (again.width(), again.height()),
+            (level.width(), level.height())
+        );
+    }
+
+    #[test]
+    fn round_trip_goals() {
+        let source = "######\n#+$ .#\n# *$ #\n######";
+        round_trip(source, source);
+    }
+
+    #[test]
+    fn round_trip_centred() {
+        // Small levels are padded and centred, but written without padding.
+        let source = "  #####\n###  .#\n#@ $  #\n#######";
+        let level = Level::parse(source).unwrap();
+        assert_eq!((level.width(), level.height()), (MIN_WIDTH, MIN_HEIGHT));
+
+        round_trip(source, source);
+    }
+
+    #[test]
+    fn round_trip_wide() {
+        let wall = "#".repeat(MIN_WIDTH + 10);
+        let source = format!("{}\n#@$.{}#\n{}", wall, " ".repeat(MIN_WIDTH + 5), wall);
+        let level = Level::parse(&source).unwrap();
+        assert_eq!(level.width(), MIN_WIDTH + 12);
+
+        round_trip(&source, &source);
+    }
+
+    #[test]
+    fn round_trip_run_length() {
+        let expected = "######\n#+$  #\n#  *.#\n#   $#\n######";
+
+        round_trip("6#\n#+$2-#\n#2-*.#\n#3-$#\n6#", expected);
+        round_trip("6#|#+$--#|#__*.#|#3 $#|6#", expected);
+    }
+
+    #[test]
+    fn no_player() {
+        assert_eq!(Level::parse("").err(), Some(LevelError::NoPlayer));
+        assert_eq!(
+            Level::parse("#####\n# $.#\n#####").err(),
+            Some(LevelError::NoPlayer)
+        );
+    }
+
+    #[test]
+    fn multiple_players() {
+        assert_eq!(
+            Level::parse("#####\n#@$.#\n# + #\n#####").err(),
+            Some(LevelError::MultiplePlayers { line: 3, column: 3 })
+        );
+    }
+
+    #[test]
+    fn box_goal_mismatch() {
+        assert_eq!(
+            Level::parse("####\n#@$#\n####").err(),
+            Some(LevelError::BoxGoalMismatch { boxes: 1, goals: 0 })
+        );
+        assert_eq!(
+            Level::parse("#####\n#@*.#\n#####").err(),
+            Some(LevelError::BoxGoalMismatch { boxes: 1, goals: 2 })
+        );
+    }
+
+    #[test]
+    fn unknown_character() {
+        assert_eq!(
+            Level::parse("#####\n#@$.#\n# x #\n#####").err(),
+            Some(LevelError::UnknownCharacter {
+                character: 'x',
+                line: 3,
+                column: 3,
+            })
+        );
+        // Columns count the tiles after run-length encoding is expanded.
+        assert_eq!(
+            Level::parse("6#|#@$.2x#|6#").err(),
+            Some(LevelError::UnknownCharacter {
+                character: 'x',
+                line: 2,
+                column: 5,
+            })
+        );
+    }
+
+    #[test]
+    fn not_enclosed() {
+        assert_eq!(
+            Level::parse("#####\n#@$. \n#####").err(),
+            Some(LevelError::NotEnclosed { line: 2, column: 4 })
+        );
+        assert_eq!(
+            Level::parse("#####\n#@$.#\n## ##").err(),
+            Some(LevelError::NotEnclosed { line: 3, column: 3 })
+        );
+        assert_eq!(
+            Level::parse("#####\n#@$.#\n# #\n###").err(),
+            Some(LevelError::NotEnclosed { line: 3, column: 4 })
+        );
+    }
+
+    #[test]
+    fn too_large() {
+        let wide = format!("{}\n#@$.#", "#".repeat(MAX_WIDTH + 1));
+        assert_eq!(
+            Level::parse(&wide).err(),
+            Some(LevelError::TooLarge {
+                width: MAX_WIDTH + 1,
+                height: 2,
+            })
+        );
+
+        let high = vec!["#@$.#"; MAX_HEIGHT + 1].join("\n");
+        assert_eq!(
+            Level::parse(&high).err(),
+            Some(LevelError::TooLarge {
+                width: 5,
+                height: MAX_HEIGHT + 1,
+            })
+        );
+    }
+
+    #[test]
+    fn trailing_count() {
+        assert_eq!(
+            Level::parse("#####3\n#@$.#\n#####").err(),
+            Some(LevelError::TrailingCount { line: 1, column: 6 })
+        );
+        // The column is where the count would have been expanded.
+        assert_eq!(
+            Level::parse("#####\n#@$.#|3# 2").err(),
+            Some(LevelError::TrailingCount { line: 3, column: 5 })
+        );
+    }
+
+    #[test]
+    fn too_large_run_length() {
+        assert_eq!(
+            Level::parse("#@$.300000000#").err(),
+            Some(LevelError::TooLarge {
+                width: 300_000_004,
+                height: 1,
+            })
+        );
+        assert!(matches!(
+            Level::parse("#@$.99999999999999999999#"),
+            Err(LevelError::TooLarge { .. })
+        ));
+
+        // Trailing whitespace is trimmed, so it doesn't count.
+        let padded = format!("#####{}\n#@$.#\n#####", " ".repeat(MAX_WIDTH));
+        assert!(Level::parse(&padded).is_ok());
+    }
+
+    const CANONICAL: &str = "#######\n#@ $ .#\n# # $ #\n#.    #\n#######";
+
+    fn canonical(source: &str) -> String {
+        Level::parse(source).unwrap().canonical()
+    }
+
+    #[test]
+    fn canonical_rotated() {
+        let rotated = "#####\n#. @#\n# # #\n#  $#\n# $ #\n#  .#\n#####";
+        assert_eq!(canonical(rotated), canonical(CANONICAL));
+    }
+
+    #[test]
+    fn canonical_mirrored() {
+        let mirrored = "#######\n#. $ @#\n# $ # #\n#    .#\n#######";
+        assert_eq!(canonical(mirrored), canonical(CANONICAL));
+    }
+
+    #[test]
+    fn canonical_player_moved() {
+        let moved = "#######\n#  $ .#\n# # $ #\n#.  @ #\n#######";
+        assert_eq!(canonical(moved), canonical(CANONICAL));
+    }
+
+    #[test]
+    fn canonical_decoration() {
+        let decorated = "  ###\n#######\n#@ $ .#\n# # $ #\n#.    #\n#######\n    ##";
+        assert_eq!(canonical(decorated), canonical(CANONICAL));
+    }
+
+    #[test]
+    fn canonical_different() {
+        let different = "#######\n#@ $. #\n# # $ #\n#.    #\n#######";
+        assert_ne!(canonical(different), canonical(CANONICAL));
+    }
+
+    /// Draws the walls, goals and dead squares of a level, marking dead
+    /// squares with `x`, cropped to the level like `to_xsb`.
+    fn dead_squares(source: &str) -> String {
+        let level = Level::parse(source).unwrap();
+        let dead = level.dead_squares();
+
+        let lines: Vec<String> = (0..level.height)
+            .rev()
+            .map(|y| {
+                (0..level.width)
+                    .map(|x| match level.tiles[y][x] {
+                        _ if dead[y][x] => 'x',
+                        Tile::Wall => '#',
+                        Tile::Goal | Tile::BoxInGoal | Tile::PlayerInGoal => '.',
+                        _ => ' ',
+                    })
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        let indent = lines
+            .iter()
+            .map(|line| line.len() - line.trim_start().len())
+            .min()
+            .unwrap_or(0);
+
+        lines
+            .iter()
+            .map(|line| &line[indent..])
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn dead_squares_along_walls() {
+        // The goal in the corner stays live, as do the walls leading away
+        // from it, while the other corners and walls are dead.
+        assert_eq!(
+            dead_squares("#######\n#.    #\n#  $  #\n#    @#\n#######"),
+            "#######\n#.   x#\n#    x#\n#xxxxx#\n#######"
+        );
+    }
+
+    #[test]
+    fn dead_squares_in_a_side_room() {
+        // Boxes can be brought to the goal from the middle of the room below
+        // the corridor, but not from along its walls, which have no goal.
+        assert_eq!(
+            dead_squares("########\n#@ $  .#\n#   ####\n#   #\n#####"),
+            "########\n#x    .#\n#x  ####\n#xxx#\n#####"
+        );
     }
 }
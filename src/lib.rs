@@ -0,0 +1,13 @@
+//! The rules and file formats of the game, kept apart from the rendering so
+//! that command line tools can use them too.
+
+pub mod autotile;
+pub mod collection;
+pub mod deadlock;
+pub mod game;
+pub mod generator;
+pub mod history;
+pub mod level;
+pub mod lurd;
+pub mod solution;
+pub mod solver;
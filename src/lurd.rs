@@ -0,0 +1,212 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::game::{Direction, GameState, Move};
+use crate::history::Step;
+use crate::level::Level;
+
+/// The LURD letter for a step: `l`, `u`, `r` or `d` for the direction,
+/// uppercase when the step pushed a box.
+pub fn step_to_char(step: Step) -> char {
+    let c = match step.direction {
+        Direction::Left => 'l',
+        Direction::Up => 'u',
+        Direction::Right => 'r',
+        Direction::Down => 'd',
+    };
+
+    if step.push {
+        c.to_ascii_uppercase()
+    } else {
+        c
+    }
+}
+
+/// The step a LURD letter stands for, if it is one.
+pub fn char_to_step(c: char) -> Option<Step> {
+    let direction = match c.to_ascii_lowercase() {
+        'l' => Direction::Left,
+        'u' => Direction::Up,
+        'r' => Direction::Right,
+        'd' => Direction::Down,
+        _ => return None,
+    };
+
+    Some(Step {
+        direction,
+        push: c.is_ascii_uppercase(),
+    })
+}
+
+/// Writes steps down in LURD notation, the format most Sokoban tools use
+/// for solutions.
+pub fn to_lurd(steps: &[Step]) -> String {
+    steps.iter().cloned().map(step_to_char).collect()
+}
+
+/// Why a step of a solution couldn't be replayed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StepError {
+    /// A character other than a LURD letter or whitespace.
+    UnknownCharacter(char),
+    /// A wall, or a box that can't be pushed, is in the way.
+    Blocked,
+    /// The step is uppercase but doesn't push a box.
+    NotAPush,
+    /// The step is lowercase but pushes a box.
+    UnexpectedPush,
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepError::UnknownCharacter(c) => write!(f, "unknown character {:?}", c),
+            StepError::Blocked => write!(f, "the way is blocked"),
+            StepError::NotAPush => write!(f, "written as a push but moves no box"),
+            StepError::UnexpectedPush => write!(f, "written as a move but pushes a box"),
+        }
+    }
+}
+
+impl Error for StepError {}
+
+/// The first step of a solution that couldn't be replayed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IllegalStep {
+    /// 0-based index of the step, not counting whitespace.
+    pub index: usize,
+    pub error: StepError,
+}
+
+/// The result of replaying a solution with `verify`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Verification {
+    pub illegal_step: Option<IllegalStep>,
+    /// Whether every box was on a goal where the replay stopped.
+    pub solved: bool,
+    /// Moves and pushes replayed before stopping.
+    pub moves: usize,
+    pub pushes: usize,
+}
+
+impl Verification {
+    /// Whether every step could be replayed and the level ended up solved.
+    pub fn is_valid(&self) -> bool {
+        self.illegal_step.is_none() && self.solved
+    }
+}
+
+/// Replays a LURD solution on a level with the rules of the game, stopping
+/// at the first step that can't be taken as written. Whitespace is ignored,
+/// so solutions can be wrapped over several lines.
+pub fn verify(level: &Level, lurd: &str) -> Verification {
+    let mut game = GameState::new(level);
+    let mut illegal_step = None;
+
+    for (index, c) in lurd.chars().filter(|c| !c.is_whitespace()).enumerate() {
+        if let Err(error) = replay_step(&mut game, c) {
+            illegal_step = Some(IllegalStep { index, error });
+            break;
+        }
+    }
+
+    Verification {
+        illegal_step,
+        solved: game.is_solved(),
+        moves: game.moves(),
+        pushes: game.pushes(),
+    }
+}
+
+fn replay_step(game: &mut GameState, c: char) -> Result<(), StepError> {
+    let step = char_to_step(c).ok_or(StepError::UnknownCharacter(c))?;
+
+    // A step written down wrongly is taken back, so the counts only cover
+    // the steps that were replayed.
+    match (game.try_move(step.direction), step.push) {
+        (Move::Blocked, _) => Err(StepError::Blocked),
+        (Move::Walk { .. }, true) => {
+            game.undo(step.direction, false);
+            Err(StepError::NotAPush)
+        }
+        (Move::Push { .. }, false) => {
+            game.undo(step.direction, true);
+            Err(StepError::UnexpectedPush)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level() -> Level {
+        Level::parse("#######\n#@ $ .#\n#######").unwrap()
+    }
+
+    #[test]
+    fn valid() {
+        let verification = verify(&level(), "rR\nR");
+
+        assert!(verification.is_valid());
+        assert_eq!((verification.moves, verification.pushes), (3, 2));
+    }
+
+    #[test]
+    fn not_a_push() {
+        let verification = verify(&level(), "RRR");
+
+        assert_eq!(
+            verification.illegal_step,
+            Some(IllegalStep {
+                index: 0,
+                error: StepError::NotAPush,
+            })
+        );
+        assert!(!verification.solved);
+        assert_eq!((verification.moves, verification.pushes), (0, 0));
+    }
+
+    #[test]
+    fn unexpected_push() {
+        let verification = verify(&level(), "rrR");
+
+        assert_eq!(
+            verification.illegal_step,
+            Some(IllegalStep {
+                index: 1,
+                error: StepError::UnexpectedPush,
+            })
+        );
+        assert_eq!((verification.moves, verification.pushes), (1, 0));
+    }
+
+    #[test]
+    fn unknown_character() {
+        let verification = verify(&level(), "r R\nx");
+
+        // Whitespace doesn't count towards the index.
+        assert_eq!(
+            verification.illegal_step,
+            Some(IllegalStep {
+                index: 2,
+                error: StepError::UnknownCharacter('x'),
+            })
+        );
+        assert_eq!((verification.moves, verification.pushes), (2, 1));
+    }
+
+    #[test]
+    fn blocked() {
+        let verification = verify(&level(), "l");
+
+        assert_eq!(
+            verification.illegal_step,
+            Some(IllegalStep {
+                index: 0,
+                error: StepError::Blocked,
+            })
+        );
+    }
+}
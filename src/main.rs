@@ -3,17 +3,21 @@ use amethyst::{
     input::InputBundle,
     prelude::*,
     renderer::{
-        Blend, ColorMask, DepthMode, DisplayConfig, DrawFlat, DrawFlat2D, Equation, Factor,
-        Pipeline, PosNormTex, RenderBundle, Stage, ALPHA,
+        ColorMask, DepthMode, DisplayConfig, DrawFlat2D, Pipeline, RenderBundle, Stage, ALPHA,
     },
+    ui::{DrawUi, UiBundle},
     utils::application_root_dir,
 };
 
-mod level;
+mod jobs;
+mod replay;
 mod sokoban;
 mod systems;
+mod theme;
+mod transition;
 
-use crate::sokoban::Sokoban;
+use crate::sokoban::{load_theme, Sokoban};
+use crate::theme::GameConfig;
 
 fn main() -> amethyst::Result<()> {
     amethyst::start_logger(Default::default());
@@ -21,6 +25,13 @@ fn main() -> amethyst::Result<()> {
     let display_path = format!("{}/resources/display_config.ron", application_root_dir());
     let config = DisplayConfig::load(&display_path);
 
+    let game_config_path = format!("{}/resources/game_config.ron", application_root_dir());
+    let game_config = GameConfig::load(&game_config_path);
+
+    // The clear color is fixed once the pipeline is built, so it follows the
+    // configured theme even for levels that pick another one.
+    let theme = load_theme(&game_config.theme);
+
     let binding_path = format!("{}/resources/bindings_config.ron", application_root_dir());
 
     let input_bundle =
@@ -28,26 +39,56 @@ fn main() -> amethyst::Result<()> {
 
     let pipe = Pipeline::build().with_stage(
         Stage::with_backbuffer()
-            .clear_target([0.00196, 0.23726, 0.21765, 1.0], 1.0)
+            .clear_target(theme.clear_color, 1.0)
             // .with_pass(DrawFlat::<PosNormTex>::new())
             .with_pass(DrawFlat2D::new().with_transparency(
                 ColorMask::all(),
                 ALPHA,
                 Some(DepthMode::LessEqualWrite),
-            )),
+            ))
+            .with_pass(DrawUi::new()),
     );
 
     let game_data = GameDataBuilder::default()
         .with_bundle(TransformBundle::new())?
         .with_bundle(
-            RenderBundle::new(pipe, Some(config))
+            RenderBundle::new(pipe, Some(config.clone()))
                 .with_sprite_sheet_processor()
                 .with_sprite_visibility_sorting(&["transform_system"]),
         )?
         .with_bundle(input_bundle)?
+        .with_bundle(UiBundle::<String, String>::new())?
         .with(systems::MoveSystem, "move_system", &[])
-        .with(systems::PlayerSystem, "player_system", &["move_system"]);
-    let mut game = Application::new("./", Sokoban, game_data)?;
+        .with(
+            systems::PlayerSystem::default(),
+            "player_system",
+            &["move_system"],
+        )
+        .with(
+            systems::HistorySystem::default(),
+            "history_system",
+            &["player_system"],
+        )
+        .with(
+            systems::DeadlockSystem::default(),
+            "deadlock_system",
+            &["player_system"],
+        )
+        .with(systems::SolverSystem::default(), "solver_system", &[])
+        .with(
+            systems::HintSystem::default(),
+            "hint_system",
+            &["history_system", "solver_system"],
+        )
+        .with(
+            systems::DebugSystem::default(),
+            "debug_system",
+            &["history_system"],
+        );
+    let mut game = Application::build("./", Sokoban::default())?
+        .with_resource(game_config)
+        .with_resource(config)
+        .build(game_data)?;
 
     game.run();
 
@@ -0,0 +1,291 @@
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    core::Time,
+    ecs::prelude::*,
+    input::InputHandler,
+    prelude::*,
+    renderer::SpriteSheetHandle,
+    ui::{get_default_font, Anchor, FontAsset, UiText, UiTransform},
+};
+use log::{error, info, warn};
+
+use amethyst_sokoban::collection::LevelCollection;
+use amethyst_sokoban::history::Step;
+use amethyst_sokoban::lurd::{char_to_step, verify};
+use amethyst_sokoban::solution::Solutions;
+
+use crate::sokoban::{load_level, unload_level, PlayState};
+
+/// The slowest and fastest a replay can run, relative to normal speed.
+const MIN_SPEED: f32 = 0.25;
+const MAX_SPEED: f32 = 8.0;
+
+/// The solution being replayed. While it is active, `PlayerSystem` takes
+/// its steps from here instead of the keyboard, and `HistorySystem` its
+/// steps back.
+#[derive(Default)]
+pub struct Replay {
+    steps: Vec<Step>,
+    /// How many of `steps` have been taken.
+    position: usize,
+    pub playing: bool,
+    /// Asks for a single step forward, or back, to be taken.
+    pub step_forward: bool,
+    pub step_back: bool,
+}
+
+impl Replay {
+    pub fn new(steps: Vec<Step>) -> Replay {
+        Replay {
+            steps,
+            ..Replay::default()
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.steps.is_empty()
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// The next step to take, if the replay is playing or a step forward
+    /// was asked for.
+    pub fn advance(&mut self) -> Option<Step> {
+        if !self.playing && !self.step_forward {
+            return None;
+        }
+        self.step_forward = false;
+
+        let step = self.steps.get(self.position).cloned();
+        match step {
+            Some(_) => self.position += 1,
+            None => self.playing = false,
+        }
+
+        step
+    }
+
+    /// Whether a step back was asked for and there is one to take. Stepping
+    /// back pauses the replay.
+    pub fn retreat(&mut self) -> bool {
+        if !self.step_back {
+            return false;
+        }
+        self.step_back = false;
+
+        if self.position == 0 {
+            return false;
+        }
+
+        self.position -= 1;
+        self.playing = false;
+        true
+    }
+}
+
+/// Plays back the saved solution of the current level, starting it over
+/// first. The level starts over again when the replay ends.
+pub struct ReplayState {
+    character_handle: SpriteSheetHandle,
+    text: Option<Entity>,
+    /// Actions held down during the last frame, which are only acted on once
+    /// until they are released.
+    held: Vec<&'static str>,
+}
+
+impl ReplayState {
+    pub fn new(character_handle: SpriteSheetHandle) -> ReplayState {
+        ReplayState {
+            character_handle,
+            text: None,
+            held: Vec::new(),
+        }
+    }
+
+    fn restart_level(&self, world: &mut World) {
+        let index = world.read_resource::<PlayState>().index;
+
+        unload_level(world);
+        load_level(world, self.character_handle.clone(), index);
+    }
+
+    /// The actions pressed since the last frame.
+    fn pressed(&mut self, world: &World) -> Vec<&'static str> {
+        let input = world.read_resource::<InputHandler<String, String>>();
+        let down: Vec<&'static str> = [
+            "replay",
+            "play_pause",
+            "step_forward",
+            "step_back",
+            "faster",
+            "slower",
+        ]
+        .iter()
+        .cloned()
+        .filter(|&action| input.action_is_down(action).unwrap_or(false))
+        .collect();
+
+        let pressed = down
+            .iter()
+            .cloned()
+            .filter(|action| !self.held.contains(action))
+            .collect();
+        self.held = down;
+
+        pressed
+    }
+}
+
+/// The saved solution of the level being played, if there is a valid one.
+fn load_solution(world: &World) -> Option<Vec<Step>> {
+    let index = world.read_resource::<PlayState>().index;
+    let collection = world.read_resource::<LevelCollection>();
+    let path = Solutions::path_for(collection.path()?);
+
+    let solutions = match Solutions::load(&path) {
+        Ok(solutions) => solutions,
+        Err(err) => {
+            error!("Failed to load {}: {}", path.display(), err);
+            return None;
+        }
+    };
+
+    let solution = match solutions.get(index + 1) {
+        Some(solution) => solution,
+        None => {
+            warn!("{} has no solution for level {}", path.display(), index + 1);
+            return None;
+        }
+    };
+
+    if !verify(&collection.get(index)?.level, &solution.lurd).is_valid() {
+        warn!(
+            "The solution for level {} in {} doesn't solve it",
+            index + 1,
+            path.display()
+        );
+        return None;
+    }
+
+    Some(solution.lurd.chars().filter_map(char_to_step).collect())
+}
+
+impl SimpleState for ReplayState {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let world = data.world;
+
+        // Don't act on the key that started the replay right away.
+        self.pressed(world);
+
+        let steps = match load_solution(world) {
+            Some(steps) => steps,
+            None => return,
+        };
+
+        info!("Replaying a solution of {} steps", steps.len());
+
+        self.restart_level(world);
+        world.add_resource(Replay::new(steps));
+
+        let font = get_default_font(
+            &world.read_resource::<Loader>(),
+            &world.read_resource::<AssetStorage<FontAsset>>(),
+        );
+
+        self.text = Some(
+            world
+                .create_entity()
+                .with(UiTransform::new(
+                    "replay".to_string(),
+                    Anchor::BottomMiddle,
+                    0.0,
+                    30.0,
+                    1.0,
+                    1000.0,
+                    40.0,
+                    0,
+                ))
+                .with(UiText::new(font, String::new(), [1.0, 1.0, 1.0, 1.0], 30.0))
+                .build(),
+        );
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let world = data.world;
+
+        if let Some(text) = self.text.take() {
+            if let Err(err) = world.delete_entity(text) {
+                error!("Failed to delete the replay text: {:?}", err);
+            }
+        }
+
+        if world.read_resource::<Replay>().is_active() {
+            world.add_resource(Replay::default());
+            self.restart_level(world);
+        }
+
+        world.write_resource::<Time>().set_time_scale(1.0);
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        let world = &mut *data.world;
+
+        if !world.read_resource::<Replay>().is_active() {
+            return Trans::Pop;
+        }
+
+        let pressed = self.pressed(world);
+        if pressed.contains(&"replay") {
+            return Trans::Pop;
+        }
+
+        {
+            let mut replay = world.write_resource::<Replay>();
+            let mut time = world.write_resource::<Time>();
+
+            for action in pressed {
+                match action {
+                    "play_pause" => replay.playing = !replay.playing,
+                    "step_forward" => replay.step_forward = true,
+                    "step_back" => replay.step_back = true,
+                    "faster" => {
+                        let speed = (time.time_scale() * 2.0).min(MAX_SPEED);
+                        time.set_time_scale(speed);
+                    }
+                    "slower" => {
+                        let speed = (time.time_scale() / 2.0).max(MIN_SPEED);
+                        time.set_time_scale(speed);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let status = {
+            let replay = world.read_resource::<Replay>();
+            let time = world.read_resource::<Time>();
+
+            format!(
+                "Replay {}/{} at {}x{}",
+                replay.position(),
+                replay.len(),
+                time.time_scale(),
+                if replay.playing { "" } else { ", paused" }
+            )
+        };
+
+        if let Some(text) = self.text {
+            if let Some(ui_text) = world.write_storage::<UiText>().get_mut(text) {
+                ui_text.text = status;
+            }
+        }
+
+        Trans::None
+    }
+}
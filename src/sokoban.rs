@@ -1,35 +1,82 @@
+use std::mem;
+
 use amethyst::{
     assets::{AssetStorage, Loader},
     core::transform::Transform,
     ecs::prelude::*,
-    input::InputBundle,
+    input::InputHandler,
     prelude::*,
     renderer::{
-        Camera, DisplayConfig, DrawFlat, Pipeline, PngFormat, PosNormTex, Projection, RenderBundle,
-        SpriteRender, SpriteSheet, SpriteSheetFormat, SpriteSheetHandle, Stage, Texture,
-        TextureMetadata, Transparent,
+        Camera, DisplayConfig, PngFormat, Projection, SpriteRender, SpriteSheet, SpriteSheetFormat,
+        SpriteSheetHandle, Texture, TextureMetadata, Transparent,
     },
+    shrev::{EventChannel, ReaderId},
     utils::application_root_dir,
 };
+use log::{error, warn};
+
+use amethyst_sokoban::autotile::{AutotileRules, WallRules};
+use amethyst_sokoban::collection::LevelCollection;
+use amethyst_sokoban::deadlock::{DeadlockDetector, DeadlockKind};
+use amethyst_sokoban::game::{Direction, GameState};
+use amethyst_sokoban::history::History;
+use amethyst_sokoban::level::{Level, MIN_HEIGHT, MIN_WIDTH};
+
+use crate::replay::ReplayState;
+use crate::systems::HintSpriteSheet;
+use crate::theme::{GameConfig, Theme};
+use crate::transition::Transition;
+
+pub const TILE_SIZE: f32 = 16.0;
+
+/// Width over height of the window set up in `display_config.ron`, or of the
+/// smallest level when it doesn't give a size.
+fn aspect_ratio(config: &DisplayConfig) -> f32 {
+    match config.dimensions {
+        Some((width, height)) => width as f32 / height as f32,
+        None => MIN_WIDTH as f32 / MIN_HEIGHT as f32,
+    }
+}
 
-use crate::level::Level;
+/// The area covered by the camera: the level, widened or heightened to match
+/// the aspect ratio of the window.
+fn arena_size(width: usize, height: usize, aspect_ratio: f32) -> (f32, f32) {
+    let width = width as f32 * TILE_SIZE;
+    let height = height as f32 * TILE_SIZE;
 
-pub const ARENA_WIDTH: f32 = 320.0;
-pub const ARENA_HEIGHT: f32 = 256.0;
+    if width / height > aspect_ratio {
+        (width, width / aspect_ratio)
+    } else {
+        (height * aspect_ratio, height)
+    }
+}
+
+fn initialise_camera(world: &mut World) -> Entity {
+    let (left, right, bottom, top) = {
+        let state = world.read_resource::<PlayState>();
+        let (width, height) = match state.level.as_ref() {
+            Some(level) => (level.width(), level.height()),
+            None => (MIN_WIDTH, MIN_HEIGHT),
+        };
+        let aspect_ratio = aspect_ratio(&world.read_resource::<DisplayConfig>());
+        let (arena_width, arena_height) = arena_size(width, height, aspect_ratio);
+
+        // Tiles are centered on their position, and the level on the arena.
+        let left = -(arena_width - width as f32 * TILE_SIZE + TILE_SIZE) / 2.0;
+        let bottom = -(arena_height - height as f32 * TILE_SIZE + TILE_SIZE) / 2.0;
+
+        (left, left + arena_width, bottom, bottom + arena_height)
+    };
 
-fn initialise_camera(world: &mut World) {
     let mut transform = Transform::default();
     transform.set_z(1.0);
     world
         .create_entity()
         .with(Camera::from(Projection::orthographic(
-            0.0,
-            ARENA_WIDTH,
-            0.0,
-            ARENA_HEIGHT,
+            left, right, bottom, top,
         )))
         .with(transform)
-        .build();
+        .build()
 }
 
 fn load_sprite_sheet(world: &mut World, name: &str) -> SpriteSheetHandle {
@@ -56,31 +103,100 @@ fn load_sprite_sheet(world: &mut World, name: &str) -> SpriteSheetHandle {
     )
 }
 
+/// Loads a theme from `texture/<name>_theme.ron`.
+pub fn load_theme(name: &str) -> Theme {
+    Theme::load(format!(
+        "{}/texture/{}_theme.ron",
+        application_root_dir(),
+        name
+    ))
+}
+
+/// Loads ground autotile rules from a file in `texture/`.
+fn load_autotile_rules(file: &str) -> AutotileRules {
+    AutotileRules::load(format!("{}/texture/{}", application_root_dir(), file))
+}
+
+/// Loads wall autotile rules from a file in `texture/`.
+fn load_wall_rules(file: &str) -> WallRules {
+    WallRules::load(format!("{}/texture/{}", application_root_dir(), file))
+}
+
 fn create_ground(
     world: &mut World,
     sprite_sheet_handle: SpriteSheetHandle,
     x: usize,
     y: usize,
     sprite_number: usize,
-) {
+) -> Entity {
     let mut local_transform = Transform::default();
-    local_transform.set_xyz(x as f32 * 16.0, y as f32 * 16.0, -100.0);
+    local_transform.set_xyz(x as f32 * TILE_SIZE, y as f32 * TILE_SIZE, -100.0);
 
     let sprite_render = SpriteRender {
         sprite_sheet: sprite_sheet_handle,
-        sprite_number: sprite_number,
+        sprite_number,
     };
 
     world
         .create_entity()
         .with(sprite_render)
         .with(local_transform)
-        .build();
+        .build()
 }
 
-fn create_player(world: &mut World, sprite_sheet_handle: SpriteSheetHandle, x: usize, y: usize) {
+fn create_wall(
+    world: &mut World,
+    sprite_sheet_handle: SpriteSheetHandle,
+    x: usize,
+    y: usize,
+    sprite_number: usize,
+) -> Entity {
+    let mut local_transform = Transform::default();
+    local_transform.set_xyz(x as f32 * TILE_SIZE, y as f32 * TILE_SIZE, -90.0);
+
+    let sprite_render = SpriteRender {
+        sprite_sheet: sprite_sheet_handle,
+        sprite_number,
+    };
+
+    world
+        .create_entity()
+        .with(sprite_render)
+        .with(local_transform)
+        .with(Transparent)
+        .build()
+}
+
+fn create_shade(
+    world: &mut World,
+    sprite_sheet_handle: SpriteSheetHandle,
+    x: usize,
+    y: usize,
+) -> Entity {
+    let mut local_transform = Transform::default();
+    local_transform.set_xyz(x as f32 * TILE_SIZE, y as f32 * TILE_SIZE, -80.0);
+
+    let sprite_render = SpriteRender {
+        sprite_sheet: sprite_sheet_handle,
+        sprite_number: 0,
+    };
+
+    world
+        .create_entity()
+        .with(sprite_render)
+        .with(local_transform)
+        .with(Transparent)
+        .build()
+}
+
+fn create_player(
+    world: &mut World,
+    sprite_sheet_handle: SpriteSheetHandle,
+    x: usize,
+    y: usize,
+) -> Entity {
     let mut local_transform = Transform::default();
-    local_transform.set_xyz(x as f32 * 16.0, y as f32 * 16.0, 0.0);
+    local_transform.set_xyz(x as f32 * TILE_SIZE, y as f32 * TILE_SIZE, 0.0);
 
     let sprite_render = SpriteRender {
         sprite_sheet: sprite_sheet_handle,
@@ -94,16 +210,22 @@ fn create_player(world: &mut World, sprite_sheet_handle: SpriteSheetHandle, x: u
         .with(Transparent)
         .with(Player {})
         .with(Movable { moving_to: None })
-        .build();
+        .build()
 }
 
-fn create_goal(world: &mut World, sprite_sheet_handle: SpriteSheetHandle, x: usize, y: usize) {
+fn create_goal(
+    world: &mut World,
+    sprite_sheet_handle: SpriteSheetHandle,
+    x: usize,
+    y: usize,
+    sprite_number: usize,
+) -> Entity {
     let mut local_transform = Transform::default();
-    local_transform.set_xyz(x as f32 * 16.0, y as f32 * 16.0, -50.0);
+    local_transform.set_xyz(x as f32 * TILE_SIZE, y as f32 * TILE_SIZE, -50.0);
 
     let sprite_render = SpriteRender {
         sprite_sheet: sprite_sheet_handle,
-        sprite_number: 32,
+        sprite_number,
     };
 
     world
@@ -111,16 +233,22 @@ fn create_goal(world: &mut World, sprite_sheet_handle: SpriteSheetHandle, x: usi
         .with(sprite_render)
         .with(local_transform)
         .with(Transparent)
-        .build();
+        .build()
 }
 
-fn create_box(world: &mut World, sprite_sheet_handle: SpriteSheetHandle, x: usize, y: usize) {
+fn create_box(
+    world: &mut World,
+    sprite_sheet_handle: SpriteSheetHandle,
+    x: usize,
+    y: usize,
+    sprite_number: usize,
+) -> Entity {
     let mut local_transform = Transform::default();
-    local_transform.set_xyz(x as f32 * 16.0, y as f32 * 16.0, -50.0);
+    local_transform.set_xyz(x as f32 * TILE_SIZE, y as f32 * TILE_SIZE, -50.0);
 
     let sprite_render = SpriteRender {
         sprite_sheet: sprite_sheet_handle,
-        sprite_number: 31,
+        sprite_number,
     };
 
     world
@@ -130,57 +258,162 @@ fn create_box(world: &mut World, sprite_sheet_handle: SpriteSheetHandle, x: usiz
         .with(Transparent)
         .with(Box {})
         .with(Movable { moving_to: None })
-        .build();
+        .build()
 }
 
-fn load_level(
-    world: &mut World,
-    outdoor_handle: SpriteSheetHandle,
-    character_handle: SpriteSheetHandle,
-) {
-    // let level = Level::parse("#######\n#     #\n# @   #\n#  $  #\n#   . #\n#     #\n#######");
-    let level = Level::parse("        #######\n#########     #\n#..  ##@# ### #####\n#..          $  $ #\n#..  ##### ## #   #\n######   # ## # ###\n         # $ $  #\n         ##$  ###\n          #  $#\n          #   #\n          #####");
-
-    for (y, line) in level.ground().iter().enumerate() {
-        for (x, &sprite_number) in line.iter().enumerate() {
-            print!("{:x}", sprite_number);
-            if sprite_number > 0 {
-                create_ground(world, outdoor_handle.clone(), x, y, sprite_number)
+/// Loads `levels/default.sok` as the `LevelCollection` resource.
+fn load_collection(world: &mut World) {
+    let path = format!("{}/levels/default.sok", application_root_dir());
+    let collection = match LevelCollection::load(&path) {
+        Ok(collection) => collection,
+        Err(err) => {
+            error!("Failed to load {}: {}", path, err);
+            LevelCollection::default()
+        }
+    };
+
+    for err in collection.errors() {
+        error!("Skipped a level of {}: {}", path, err);
+    }
+
+    for (duplicate, original) in collection.duplicates() {
+        warn!(
+            "Level {} of {} is a duplicate of level {}",
+            duplicate + 1,
+            path,
+            original + 1
+        );
+    }
+
+    world.add_resource(collection);
+}
+
+/// Creates the entities for the level at `index` of the `LevelCollection`
+/// resource and sets up the `PlayState` resource to play it. Returns `false`
+/// if the collection has no such level.
+pub fn load_level(world: &mut World, character_handle: SpriteSheetHandle, index: usize) -> bool {
+    let (level, theme_name) = {
+        let collection = world.read_resource::<LevelCollection>();
+        let entry = match collection.get(index) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        // A level can pick its own theme, or share the one of its
+        // collection, before falling back on the one in `game_config.ron`.
+        let theme_name = entry
+            .metadata
+            .get("Theme")
+            .or_else(|| collection.metadata.get("Theme"))
+            .map(str::to_string);
+
+        (entry.level.clone(), theme_name)
+    };
+    let game = GameState::new(&level);
+
+    let (default_theme, shade_dead_squares) = {
+        let config = world.read_resource::<GameConfig>();
+        (config.theme.clone(), config.shade_dead_squares)
+    };
+    let theme_name = theme_name.unwrap_or(default_theme);
+    let theme = load_theme(&theme_name);
+    let sprite_sheet_handle = load_sprite_sheet(world, &theme.spritesheet);
+    let ground_rules = load_autotile_rules(&theme.ground);
+    let wall_rules = load_wall_rules(&theme.walls);
+
+    let mut entities = Vec::new();
+
+    for (y, line) in level.ground(&ground_rules).iter().enumerate() {
+        for (x, sprite_number) in line.iter().enumerate() {
+            if let Some(sprite_number) = *sprite_number {
+                entities.push(create_ground(
+                    world,
+                    sprite_sheet_handle.clone(),
+                    x,
+                    y,
+                    sprite_number,
+                ));
+            }
+        }
+    }
+
+    for (y, line) in level.walls(&wall_rules).iter().enumerate() {
+        for (x, sprite_number) in line.iter().enumerate() {
+            if let Some(sprite_number) = *sprite_number {
+                entities.push(create_wall(
+                    world,
+                    sprite_sheet_handle.clone(),
+                    x,
+                    y,
+                    sprite_number,
+                ));
+            }
+        }
+    }
+
+    if shade_dead_squares {
+        let shade_handle = load_sprite_sheet(world, "shade");
+
+        for (y, line) in level.dead_squares().iter().enumerate() {
+            for (x, &dead) in line.iter().enumerate() {
+                if dead {
+                    entities.push(create_shade(world, shade_handle.clone(), x, y));
+                }
             }
         }
-        println!("");
     }
 
     for (x, y) in level.goals_pos() {
-        create_goal(world, outdoor_handle.clone(), x, y);
+        entities.push(create_goal(
+            world,
+            sprite_sheet_handle.clone(),
+            x,
+            y,
+            theme.goal_sprite,
+        ));
     }
 
     for (x, y) in level.boxes_pos() {
-        create_box(world, outdoor_handle.clone(), x, y);
+        let sprite_number = theme.box_sprite_for(game.is_goal((x, y)));
+        entities.push(create_box(
+            world,
+            sprite_sheet_handle.clone(),
+            x,
+            y,
+            sprite_number,
+        ));
     }
 
     let player_pos = level.player_pos();
-    create_player(world, character_handle, player_pos.0, player_pos.1);
-
-    world.add_resource(PlayState { level: Some(level) });
+    entities.push(create_player(
+        world,
+        character_handle,
+        player_pos.0,
+        player_pos.1,
+    ));
+
+    world.add_resource(theme);
+    world.add_resource(PlayState {
+        index,
+        deadlocks: DeadlockDetector::new(&level),
+        game: Some(game),
+        level: Some(level),
+        history: History::default(),
+        entities,
+    });
+
+    let camera = initialise_camera(world);
+    world.write_resource::<PlayState>().entities.push(camera);
+
+    true
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
-pub enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
-}
+/// Deletes the entities created by `load_level`.
+pub fn unload_level(world: &mut World) {
+    let entities = mem::take(&mut world.write_resource::<PlayState>().entities);
 
-impl Direction {
-    pub fn to_velocity(&self, scale: f32) -> (f32, f32) {
-        match self {
-            Direction::Up => (0.0, scale),
-            Direction::Down => (0.0, -scale),
-            Direction::Left => (-scale, 0.0),
-            Direction::Right => (scale, 0.0),
-        }
+    if let Err(err) = world.delete_entities(&entities) {
+        error!("Failed to delete the entities of the level: {:?}", err);
     }
 }
 
@@ -204,12 +437,45 @@ impl Component for Movable {
     type Storage = DenseVecStorage<Self>;
 }
 
+/// Sent by `PlayerSystem` when a push solves the level being played.
+#[derive(Clone, Debug)]
+pub struct LevelComplete {
+    /// Index of the level in the `LevelCollection` resource.
+    pub index: usize,
+    pub moves: usize,
+    pub pushes: usize,
+    /// The steps that solved the level, in LURD notation.
+    pub solution: String,
+}
+
+/// Sent by `PlayerSystem` when a push leaves the level unsolvable.
+#[derive(Clone, Debug)]
+pub struct Deadlock {
+    /// Index of the level in the `LevelCollection` resource.
+    pub index: usize,
+    pub kind: DeadlockKind,
+    /// Whether the push was taken back, as `auto_undo_deadlocks` asks.
+    pub undone: bool,
+}
+
 #[derive(Default)]
 pub struct PlayState {
+    /// Index of the level in the `LevelCollection` resource.
+    pub index: usize,
     pub level: Option<Level>,
+    pub game: Option<GameState>,
+    pub history: History,
+    pub deadlocks: DeadlockDetector,
+    /// Everything `load_level` created, to be deleted by `unload_level`.
+    pub entities: Vec<Entity>,
 }
 
-pub struct Sokoban;
+#[derive(Default)]
+pub struct Sokoban {
+    character_handle: Option<SpriteSheetHandle>,
+    complete_reader: Option<ReaderId<LevelComplete>>,
+    replay_held: bool,
+}
 
 impl SimpleState for Sokoban {
     fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
@@ -217,11 +483,61 @@ impl SimpleState for Sokoban {
 
         // Load the spritesheet necessary to render the graphics.
         let character_handle = load_sprite_sheet(world, "character");
-        let outdoor_handle = load_sprite_sheet(world, "outdoor");
+        let hint_handle = load_sprite_sheet(world, "hint");
+        world.add_resource(HintSpriteSheet(hint_handle));
 
         // world.register::<Ground>(); // <- add this line temporarily
 
-        load_level(world, outdoor_handle.clone(), character_handle.clone());
-        initialise_camera(world);
+        load_collection(world);
+        if !load_level(world, character_handle.clone(), 0) {
+            error!("There are no levels to play");
+        }
+
+        self.character_handle = Some(character_handle);
+        self.complete_reader = Some(
+            world
+                .write_resource::<EventChannel<LevelComplete>>()
+                .register_reader(),
+        );
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        let (reader, character_handle) = match (
+            self.complete_reader.as_mut(),
+            self.character_handle.as_ref(),
+        ) {
+            (Some(reader), Some(character_handle)) => (reader, character_handle),
+            _ => return Trans::None,
+        };
+
+        let completed = data
+            .world
+            .read_resource::<EventChannel<LevelComplete>>()
+            .read(reader)
+            .last()
+            .cloned();
+
+        if let Some(completed) = completed {
+            return Trans::Push(std::boxed::Box::new(Transition::new(
+                completed,
+                character_handle.clone(),
+            )));
+        }
+
+        let replay = data
+            .world
+            .read_resource::<InputHandler<String, String>>()
+            .action_is_down("replay")
+            .unwrap_or(false);
+        let replay_pressed = replay && !self.replay_held;
+        self.replay_held = replay;
+
+        if replay_pressed {
+            return Trans::Push(std::boxed::Box::new(ReplayState::new(
+                character_handle.clone(),
+            )));
+        }
+
+        Trans::None
     }
 }
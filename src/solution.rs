@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A solution to a level in LURD notation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Solution {
+    pub title: Option<String>,
+    pub lurd: String,
+}
+
+impl Solution {
+    pub fn moves(&self) -> usize {
+        self.lurd
+            .chars()
+            .filter(|c| c.is_ascii_alphabetic())
+            .count()
+    }
+
+    pub fn pushes(&self) -> usize {
+        self.lurd.chars().filter(|c| c.is_ascii_uppercase()).count()
+    }
+
+    /// Whether this solution needs fewer moves than `other`, or as many
+    /// moves but fewer pushes.
+    pub fn is_better_than(&self, other: &Solution) -> bool {
+        (self.moves(), self.pushes()) < (other.moves(), other.pushes())
+    }
+}
+
+/// The best known solutions to the levels of a collection, by 1-based level
+/// number, as kept in a `<collection>_solutions.txt` file next to it:
+///
+/// ```text
+/// Level: 1
+/// Title: The first level
+/// Solution: rrdLLuR
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Solutions {
+    solutions: BTreeMap<usize, Solution>,
+}
+
+impl Solutions {
+    /// Where the solutions for the collection at `path` are kept.
+    pub fn path_for<P: AsRef<Path>>(path: P) -> PathBuf {
+        let path = path.as_ref();
+        let stem = path
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+
+        path.with_file_name(format!("{}_solutions.txt", stem))
+    }
+
+    /// Reads a solutions file, which is taken to be empty if it doesn't
+    /// exist yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Solutions> {
+        match fs::read_to_string(path) {
+            Ok(source) => Ok(Solutions::parse(&source)),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(Solutions::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Parses a solutions file. Lines other than `Level:`, `Title:` and
+    /// `Solution:` are skipped, as are solutions without a level number.
+    pub fn parse(source: &str) -> Solutions {
+        let mut solutions = Solutions::default();
+        let mut number = None;
+        let mut title = None;
+
+        for line in source.lines() {
+            let (key, value) = match line.find(':') {
+                Some(colon) => (line[..colon].trim(), line[colon + 1..].trim()),
+                None => continue,
+            };
+
+            match key.to_lowercase().as_str() {
+                "level" => {
+                    number = value.parse().ok();
+                    title = None;
+                }
+                "title" => title = Some(value.to_string()),
+                "solution" => {
+                    if let Some(number) = number.take() {
+                        solutions.solutions.insert(
+                            number,
+                            Solution {
+                                title: title.take(),
+                                lurd: value.to_string(),
+                            },
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        solutions
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn get(&self, number: usize) -> Option<&Solution> {
+        self.solutions.get(&number)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Solution)> {
+        self.solutions
+            .iter()
+            .map(|(&number, solution)| (number, solution))
+    }
+
+    /// Keeps `solution` for the level if it is the first one for it, or
+    /// better than the one kept so far. Returns whether it was kept.
+    pub fn insert(&mut self, number: usize, solution: Solution) -> bool {
+        match self.solutions.get(&number) {
+            Some(best) if !solution.is_better_than(best) => false,
+            _ => {
+                self.solutions.insert(number, solution);
+                true
+            }
+        }
+    }
+}
+
+impl fmt::Display for Solutions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (number, solution)) in self.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+
+            writeln!(f, "Level: {}", number)?;
+            if let Some(title) = &solution.title {
+                writeln!(f, "Title: {}", title)?;
+            }
+            writeln!(f, "Solution: {}", solution.lurd)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str =
+        "Level: 1\nTitle: The first level\nSolution: rrdLLuR\n\nLevel: 3\nSolution: RR\n";
+
+    #[test]
+    fn parse() {
+        let solutions = Solutions::parse(SOURCE);
+
+        assert_eq!(
+            solutions.get(1),
+            Some(&Solution {
+                title: Some("The first level".to_string()),
+                lurd: "rrdLLuR".to_string(),
+            })
+        );
+        assert_eq!(solutions.get(2), None);
+        assert_eq!(solutions.get(3).map(|solution| solution.pushes()), Some(2));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!(
+            "amethyst_sokoban_solutions_{}.txt",
+            std::process::id()
+        ));
+
+        Solutions::parse(SOURCE).save(&path).unwrap();
+        let loaded = Solutions::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap().to_string(), SOURCE);
+    }
+
+    #[test]
+    fn keeps_the_better_solution() {
+        let mut solutions = Solutions::parse(SOURCE);
+        let solution = |lurd: &str| Solution {
+            title: None,
+            lurd: lurd.to_string(),
+        };
+
+        assert!(!solutions.insert(3, solution("rLR")));
+        assert!(solutions.insert(3, solution("R")));
+        assert_eq!(solutions.get(3).unwrap().lurd, "R");
+    }
+}
@@ -0,0 +1,691 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::game::Direction;
+use crate::history::Step;
+use crate::level::Level;
+use crate::lurd::to_lurd;
+
+/// The direction opposite the one at `direction` in `Direction::ALL`, which
+/// is indexed to look up neighbours.
+fn opposite(direction: usize) -> usize {
+    direction ^ 1
+}
+
+/// Push distances at or above this mean that a box can't get to a goal.
+const UNREACHABLE: u32 = u32::MAX / 4;
+
+/// When the solver stops looking.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// The most positions to expand.
+    pub max_nodes: usize,
+    pub max_time: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_nodes: 1_000_000,
+            max_time: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+/// What a solution should have as few of as possible. The other one breaks
+/// ties when solving for moves.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Metric {
+    Pushes,
+    Moves,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The level was solved with as few pushes, or moves, as possible.
+    Solved {
+        lurd: String,
+        moves: usize,
+        pushes: usize,
+    },
+    /// No sequence of pushes solves the level.
+    Unsolvable,
+    /// The limits were reached before a solution was found.
+    GaveUp,
+    /// The search was stopped from the outside.
+    Cancelled,
+}
+
+/// How far along a search is.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Positions expanded so far.
+    pub nodes: usize,
+    /// Pushes made to get to the position being expanded.
+    pub depth: usize,
+    pub elapsed: Duration,
+}
+
+/// Finds a push-optimal or move-optimal solution for a level.
+pub fn solve(level: &Level, metric: Metric, limits: &Limits) -> Outcome {
+    solve_watched(level, metric, limits, |_| true)
+}
+
+/// Like `solve`, but passes the progress to `watch` every so often. The
+/// search is cancelled as soon as `watch` returns `false`.
+pub fn solve_watched<F>(level: &Level, metric: Metric, limits: &Limits, watch: F) -> Outcome
+where
+    F: FnMut(&Progress) -> bool,
+{
+    let board = Board::new(level);
+    let start = board.index(level.player_pos());
+    let boxes = level.boxes_pos().into_iter().map(|pos| board.index(pos));
+
+    Search::new(&board, metric, start, boxes.collect()).run(limits, watch)
+}
+
+/// The parts of a level that never change while solving it, with tiles
+/// numbered row by row.
+struct Board {
+    width: usize,
+    walls: Vec<bool>,
+    /// Tiles a box can never be pushed from onto a goal, as found by
+    /// `Level::dead_squares`.
+    dead: Vec<bool>,
+    goals: Vec<u16>,
+    /// The tile next to each tile in each of `Direction::ALL`.
+    neighbours: Vec<[Option<u16>; 4]>,
+    /// How many pushes it takes to get a box from each tile onto each goal,
+    /// were there no other boxes, indexed by goal and then by tile.
+    distances: Vec<Vec<u32>>,
+}
+
+impl Board {
+    fn new(level: &Level) -> Board {
+        let width = level.width();
+        let height = level.height();
+        let size = width * height;
+
+        let dead = level.dead_squares().into_iter().flatten().collect();
+        let mut walls = vec![false; size];
+        let mut neighbours = vec![[None; 4]; size];
+
+        for y in 0..height {
+            for x in 0..width {
+                let index = y * width + x;
+                walls[index] = level.is_wall(x, y);
+
+                for (d, direction) in Direction::ALL.iter().enumerate() {
+                    neighbours[index][d] = direction
+                        .apply((x, y))
+                        .filter(|&(x, y)| x < width && y < height)
+                        .map(|(x, y)| (y * width + x) as u16);
+                }
+            }
+        }
+
+        let mut board = Board {
+            width,
+            walls,
+            dead,
+            goals: Vec::new(),
+            neighbours,
+            distances: Vec::new(),
+        };
+
+        board.goals = level
+            .goals_pos()
+            .into_iter()
+            .map(|pos| board.index(pos))
+            .collect();
+        board.distances = board
+            .goals
+            .iter()
+            .map(|&goal| board.pull_distances(goal))
+            .collect();
+
+        board
+    }
+
+    fn index(&self, (x, y): (usize, usize)) -> u16 {
+        (y * self.width + x) as u16
+    }
+
+    fn neighbour(&self, tile: u16, direction: usize) -> Option<u16> {
+        self.neighbours[tile as usize][direction]
+    }
+
+    fn is_floor(&self, tile: u16) -> bool {
+        !self.walls[tile as usize]
+    }
+
+    /// Pushes needed to get a box from each tile onto `goal`, found by
+    /// pulling it away from the goal: a box can be pulled onto a tile when
+    /// the player has room to stand behind it.
+    fn pull_distances(&self, goal: u16) -> Vec<u32> {
+        let mut distances = vec![UNREACHABLE; self.walls.len()];
+        let mut queue = VecDeque::new();
+
+        distances[goal as usize] = 0;
+        queue.push_back(goal);
+
+        while let Some(tile) = queue.pop_front() {
+            for d in 0..Direction::ALL.len() {
+                let from = match self.neighbour(tile, d) {
+                    Some(from) if self.is_floor(from) => from,
+                    _ => continue,
+                };
+                let player = match self.neighbour(from, d) {
+                    Some(player) if self.is_floor(player) => player,
+                    _ => continue,
+                };
+
+                if player != tile && distances[from as usize] == UNREACHABLE {
+                    distances[from as usize] = distances[tile as usize] + 1;
+                    queue.push_back(from);
+                }
+            }
+        }
+
+        distances
+    }
+
+    fn is_dead(&self, tile: u16) -> bool {
+        self.dead[tile as usize]
+    }
+
+    /// The tiles the player can reach from `start` without moving a box,
+    /// as a map from each tile to the tile it was first reached from and
+    /// the number of steps it takes to get there.
+    fn reach(&self, start: u16, boxes: &[u16]) -> Vec<Option<(u16, u32)>> {
+        let mut reach = vec![None; self.walls.len()];
+        let mut queue = VecDeque::new();
+
+        reach[start as usize] = Some((start, 0));
+        queue.push_back(start);
+
+        while let Some(tile) = queue.pop_front() {
+            let steps = reach[tile as usize].unwrap().1;
+
+            for d in 0..Direction::ALL.len() {
+                match self.neighbour(tile, d) {
+                    Some(next)
+                        if self.is_floor(next)
+                            && reach[next as usize].is_none()
+                            && boxes.binary_search(&next).is_err() =>
+                    {
+                        reach[next as usize] = Some((tile, steps + 1));
+                        queue.push_back(next);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        reach
+    }
+
+    /// The steps walking the player from `start` to `end`, which must be
+    /// reachable.
+    fn walk(&self, start: u16, end: u16, boxes: &[u16]) -> Vec<Step> {
+        let reach = self.reach(start, boxes);
+        let mut steps = Vec::new();
+        let mut tile = end;
+
+        while tile != start {
+            let (previous, _) = reach[tile as usize].expect("walk to an unreachable tile");
+            let d = (0..Direction::ALL.len())
+                .find(|&d| self.neighbour(previous, d) == Some(tile))
+                .unwrap();
+
+            steps.push(Step {
+                direction: Direction::ALL[d],
+                push: false,
+            });
+            tile = previous;
+        }
+
+        steps.reverse();
+        steps
+    }
+
+    /// A lower bound on the pushes left: the cheapest way of sending every
+    /// box to a goal of its own, or `UNREACHABLE` if there is none.
+    fn lower_bound(&self, boxes: &[u16]) -> u32 {
+        let costs: Vec<Vec<u32>> = boxes
+            .iter()
+            .map(|&tile| {
+                self.distances
+                    .iter()
+                    .map(|distances| distances[tile as usize])
+                    .collect()
+            })
+            .collect();
+
+        min_cost_matching(&costs)
+    }
+}
+
+/// The smallest total cost of assigning each row to a column of its own,
+/// with the Hungarian algorithm. Costs of `UNREACHABLE` or more forbid an
+/// assignment.
+fn min_cost_matching(costs: &[Vec<u32>]) -> u32 {
+    let n = costs.len();
+    if n == 0 {
+        return 0;
+    }
+
+    // Potentials for rows and columns, and the row matched to each column,
+    // with everything shifted by one so that index 0 can act as a sentinel.
+    let infinity = i64::MAX / 4;
+    let mut u = vec![0i64; n + 1];
+    let mut v = vec![0i64; n + 1];
+    let mut matched = vec![0usize; n + 1];
+    let mut way = vec![0usize; n + 1];
+
+    for row in 1..=n {
+        matched[0] = row;
+        let mut column = 0;
+        let mut min = vec![infinity; n + 1];
+        let mut used = vec![false; n + 1];
+
+        loop {
+            used[column] = true;
+            let current_row = matched[column];
+            let mut delta = infinity;
+            let mut next = 0;
+
+            for j in 1..=n {
+                if used[j] {
+                    continue;
+                }
+
+                let cost = i64::from(costs[current_row - 1][j - 1]) - u[current_row] - v[j];
+                if cost < min[j] {
+                    min[j] = cost;
+                    way[j] = column;
+                }
+                if min[j] < delta {
+                    delta = min[j];
+                    next = j;
+                }
+            }
+
+            for j in 0..=n {
+                if used[j] {
+                    u[matched[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min[j] -= delta;
+                }
+            }
+
+            column = next;
+            if matched[column] == 0 {
+                break;
+            }
+        }
+
+        loop {
+            let previous = way[column];
+            matched[column] = matched[previous];
+            column = previous;
+            if column == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut total = 0u32;
+    for j in 1..=n {
+        let cost = costs[matched[j] - 1][j - 1];
+        if cost >= UNREACHABLE {
+            return UNREACHABLE;
+        }
+        total += cost;
+    }
+
+    total
+}
+
+/// A position reached while searching.
+struct Node {
+    /// The tiles of the boxes, sorted.
+    boxes: Vec<u16>,
+    /// Where the player stands, which is where the last pushed box was.
+    player: u16,
+    /// The top-left tile the player can reach, which stands for every tile
+    /// the player could walk to.
+    region: u16,
+    parent: Option<usize>,
+    /// The box pushed to get here from the parent, and the direction.
+    push: Option<(u16, usize)>,
+    moves: u32,
+    pushes: u32,
+}
+
+/// The cost of getting somewhere, in the metric being solved for and then
+/// the one breaking ties.
+type Cost = (u32, u32);
+
+/// A node waiting to be expanded, ordered so that the heap yields the one
+/// with the lowest estimated total first, preferring those furthest along.
+#[derive(PartialEq, Eq)]
+struct Open {
+    estimate: Cost,
+    cost: Cost,
+    node: usize,
+}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Open) -> Ordering {
+        (Reverse(self.estimate), self.cost, Reverse(self.node)).cmp(&(
+            Reverse(other.estimate),
+            other.cost,
+            Reverse(other.node),
+        ))
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Open) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// An A* search over box positions. Solving for pushes, positions only
+/// differ by which region the player is in, since walking is free. Solving
+/// for moves, they differ by exactly where the player stands, and each push
+/// also costs the steps walked to get behind the box.
+struct Search<'a> {
+    board: &'a Board,
+    metric: Metric,
+    nodes: Vec<Node>,
+    open: BinaryHeap<Open>,
+    /// The lowest cost found so far to each position, keyed by the boxes and
+    /// the player's region or tile.
+    best: HashMap<(Vec<u16>, u16), Cost>,
+}
+
+impl<'a> Search<'a> {
+    fn new(board: &'a Board, metric: Metric, player: u16, mut boxes: Vec<u16>) -> Search<'a> {
+        boxes.sort();
+
+        let mut search = Search {
+            board,
+            metric,
+            nodes: Vec::new(),
+            open: BinaryHeap::new(),
+            best: HashMap::new(),
+        };
+
+        search.add(Node {
+            boxes,
+            player,
+            region: 0,
+            parent: None,
+            push: None,
+            moves: 0,
+            pushes: 0,
+        });
+
+        search
+    }
+
+    /// The cost of a node, plus `remaining` pushes still to make.
+    fn cost(&self, node: &Node, remaining: u32) -> Cost {
+        match self.metric {
+            Metric::Pushes => (node.pushes + remaining, 0),
+            Metric::Moves => (node.moves + remaining, node.pushes + remaining),
+        }
+    }
+
+    fn key(&self, node: &Node) -> (Vec<u16>, u16) {
+        match self.metric {
+            Metric::Pushes => (node.boxes.clone(), node.region),
+            Metric::Moves => (node.boxes.clone(), node.player),
+        }
+    }
+
+    /// Queues a node, unless its position was already reached as cheaply or
+    /// can't be solved.
+    fn add(&mut self, mut node: Node) {
+        let estimate = self.board.lower_bound(&node.boxes);
+        if estimate >= UNREACHABLE {
+            return;
+        }
+
+        let reach = self.board.reach(node.player, &node.boxes);
+        node.region = (0..reach.len())
+            .find(|&tile| reach[tile].is_some())
+            .unwrap_or(0) as u16;
+
+        let cost = self.cost(&node, 0);
+        match self.best.entry(self.key(&node)) {
+            Entry::Occupied(mut best) => {
+                if *best.get() <= cost {
+                    return;
+                }
+                best.insert(cost);
+            }
+            Entry::Vacant(best) => {
+                best.insert(cost);
+            }
+        }
+
+        self.open.push(Open {
+            estimate: self.cost(&node, estimate),
+            cost,
+            node: self.nodes.len(),
+        });
+        self.nodes.push(node);
+    }
+
+    fn run<F>(mut self, limits: &Limits, mut watch: F) -> Outcome
+    where
+        F: FnMut(&Progress) -> bool,
+    {
+        let started = Instant::now();
+        let mut expanded = 0;
+
+        while let Some(open) = self.open.pop() {
+            let node = &self.nodes[open.node];
+            if node
+                .boxes
+                .iter()
+                .all(|tile| self.board.goals.contains(tile))
+            {
+                return self.solution(open.node);
+            }
+
+            // A cheaper way to this position turned up after it was queued.
+            if self.best[&self.key(node)] < open.cost {
+                continue;
+            }
+
+            expanded += 1;
+            if expanded > limits.max_nodes {
+                return Outcome::GaveUp;
+            }
+            if expanded % 1024 == 0 {
+                let progress = Progress {
+                    nodes: expanded,
+                    depth: node.pushes as usize,
+                    elapsed: started.elapsed(),
+                };
+
+                if !watch(&progress) {
+                    return Outcome::Cancelled;
+                }
+                if let Some(max_time) = limits.max_time {
+                    if progress.elapsed > max_time {
+                        return Outcome::GaveUp;
+                    }
+                }
+            }
+
+            self.expand(open.node);
+        }
+
+        Outcome::Unsolvable
+    }
+
+    /// Queues every position one push away from the node.
+    fn expand(&mut self, index: usize) {
+        let board = self.board;
+        let (boxes, player, moves, pushes) = {
+            let node = &self.nodes[index];
+            (node.boxes.clone(), node.player, node.moves, node.pushes)
+        };
+        let reach = board.reach(player, &boxes);
+
+        for (i, &tile) in boxes.iter().enumerate() {
+            for d in 0..Direction::ALL.len() {
+                let walked = match board
+                    .neighbour(tile, opposite(d))
+                    .and_then(|behind| reach[behind as usize])
+                {
+                    Some((_, walked)) => walked,
+                    None => continue,
+                };
+                let to = match board.neighbour(tile, d) {
+                    Some(to)
+                        if board.is_floor(to)
+                            && boxes.binary_search(&to).is_err()
+                            && !board.is_dead(to) =>
+                    {
+                        to
+                    }
+                    _ => continue,
+                };
+
+                let mut next = boxes.clone();
+                next[i] = to;
+                next.sort();
+
+                self.add(Node {
+                    boxes: next,
+                    player: tile,
+                    region: 0,
+                    parent: Some(index),
+                    push: Some((tile, d)),
+                    moves: moves + walked + 1,
+                    pushes: pushes + 1,
+                });
+            }
+        }
+    }
+
+    /// Writes out the steps leading to a solved node, walking the player
+    /// the shortest way to each push.
+    fn solution(&self, index: usize) -> Outcome {
+        let mut path = Vec::new();
+        let mut current = Some(index);
+
+        while let Some(index) = current {
+            path.push(index);
+            current = self.nodes[index].parent;
+        }
+        path.reverse();
+
+        let mut steps = Vec::new();
+        let mut player = self.nodes[path[0]].player;
+
+        for window in path.windows(2) {
+            let before = &self.nodes[window[0]];
+            let (tile, d) = self.nodes[window[1]].push.unwrap();
+            let behind = self.board.neighbour(tile, opposite(d)).unwrap();
+
+            steps.extend(self.board.walk(player, behind, &before.boxes));
+            steps.push(Step {
+                direction: Direction::ALL[d],
+                push: true,
+            });
+            player = tile;
+        }
+
+        Outcome::Solved {
+            lurd: to_lurd(&steps),
+            moves: steps.len(),
+            pushes: steps.iter().filter(|step| step.push).count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lurd::verify;
+
+    /// Solves a level, checking that the solution found really solves it in
+    /// the moves and pushes reported, and returns those.
+    fn solved(source: &str, metric: Metric) -> (usize, usize) {
+        let level = Level::parse(source).unwrap();
+        match solve(&level, metric, &Limits::default()) {
+            Outcome::Solved {
+                lurd,
+                moves,
+                pushes,
+            } => {
+                let verification = verify(&level, &lurd);
+                assert!(verification.is_valid(), "{} doesn't solve it", lurd);
+                assert_eq!((verification.moves, verification.pushes), (moves, pushes));
+                (moves, pushes)
+            }
+            outcome => panic!("expected a solution, got {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn straight_push() {
+        assert_eq!(solved("#######\n#@$ . #\n#######", Metric::Pushes), (2, 2));
+    }
+
+    #[test]
+    fn push_around_a_corner() {
+        let source = "#######\n#     #\n# @   #\n#  $  #\n#   . #\n#     #\n#######";
+        assert_eq!(solved(source, Metric::Pushes).1, 2);
+    }
+
+    #[test]
+    fn two_boxes() {
+        let source = "#######\n#.   .#\n# $ $ #\n#  @  #\n#######";
+        assert_eq!(solved(source, Metric::Pushes).1, 4);
+    }
+
+    #[test]
+    fn box_in_a_corner() {
+        let level = Level::parse("#####\n#$ .#\n# @ #\n#####").unwrap();
+        assert_eq!(
+            solve(&level, Metric::Pushes, &Limits::default()),
+            Outcome::Unsolvable
+        );
+    }
+
+    #[test]
+    fn gives_up_at_the_node_limit() {
+        let level = Level::parse("#######\n#.   .#\n# $ $ #\n#  @  #\n#######").unwrap();
+        let limits = Limits {
+            max_nodes: 1,
+            max_time: None,
+        };
+        assert_eq!(solve(&level, Metric::Pushes, &limits), Outcome::GaveUp);
+    }
+
+    #[test]
+    fn moves_and_pushes_differ() {
+        // Two pushes do it once the player has walked round below the box,
+        // while pushing it right first saves moves at the cost of two more
+        // pushes. Each solution has the fewest of the other metric among
+        // those that are optimal.
+        let source = "######\n#.   #\n#@$  #\n##  ##\n######";
+        let (push_moves, pushes) = solved(source, Metric::Pushes);
+        let (moves, move_pushes) = solved(source, Metric::Moves);
+
+        assert!(moves <= push_moves);
+        assert_eq!((push_moves, pushes), (10, 2));
+        assert_eq!((moves, move_pushes), (8, 4));
+    }
+}
@@ -0,0 +1,98 @@
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    ecs::{Entities, Entity, Read, ReadExpect, Resources, System, SystemData, WriteStorage},
+    shrev::{EventChannel, ReaderId},
+    ui::{get_default_font, Anchor, FontAsset, UiText, UiTransform},
+};
+use log::{error, warn};
+
+use crate::sokoban::{Deadlock, PlayState};
+
+/// Warns about `Deadlock` events, until the player makes their next move.
+#[derive(Default)]
+pub struct DeadlockSystem {
+    deadlock_reader: Option<ReaderId<Deadlock>>,
+    text: Option<Entity>,
+    /// The level and move count when the warning went up.
+    shown_at: Option<(usize, usize)>,
+}
+
+impl<'s> System<'s> for DeadlockSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, UiTransform>,
+        WriteStorage<'s, UiText>,
+        Read<'s, EventChannel<Deadlock>>,
+        Read<'s, PlayState>,
+        ReadExpect<'s, Loader>,
+        Read<'s, AssetStorage<FontAsset>>,
+    );
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.deadlock_reader = Some(res.fetch_mut::<EventChannel<Deadlock>>().register_reader());
+    }
+
+    fn run(
+        &mut self,
+        (entities, mut ui_transforms, mut ui_texts, deadlock_events, state, loader, fonts): Self::SystemData,
+    ) {
+        let now = state.game.as_ref().map(|game| (state.index, game.moves()));
+
+        let reader = self.deadlock_reader.as_mut().expect("setup was not called");
+        let message = deadlock_events.read(reader).last().map(|deadlock| {
+            warn!(
+                "Deadlock in level {}: {}",
+                deadlock.index + 1,
+                deadlock.kind
+            );
+
+            if deadlock.undone {
+                format!("Deadlock: {}. The push was taken back.", deadlock.kind)
+            } else {
+                format!("Deadlock: {}. Undo to get out of it.", deadlock.kind)
+            }
+        });
+
+        if message.is_some() || (self.shown_at.is_some() && self.shown_at != now) {
+            self.shown_at = None;
+
+            if let Some(text) = self.text.take() {
+                if let Err(err) = entities.delete(text) {
+                    error!("Failed to delete the deadlock warning: {:?}", err);
+                }
+            }
+        }
+
+        if let Some(message) = message {
+            self.shown_at = now;
+            self.text = Some(
+                entities
+                    .build_entity()
+                    .with(
+                        UiTransform::new(
+                            "deadlock".to_string(),
+                            Anchor::TopMiddle,
+                            0.0,
+                            -70.0,
+                            1.0,
+                            1000.0,
+                            40.0,
+                            0,
+                        ),
+                        &mut ui_transforms,
+                    )
+                    .with(
+                        UiText::new(
+                            get_default_font(&loader, &fonts),
+                            message,
+                            [1.0, 0.4, 0.4, 1.0],
+                            30.0,
+                        ),
+                        &mut ui_texts,
+                    )
+                    .build(),
+            );
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    ecs::{Entities, Entity, Read, ReadExpect, System, WriteStorage},
+    input::InputHandler,
+    ui::{get_default_font, Anchor, FontAsset, LineMode, UiText, UiTransform},
+};
+use clipboard::{ClipboardContext, ClipboardProvider};
+use log::{error, info};
+
+use amethyst_sokoban::lurd::to_lurd;
+
+use crate::jobs::SolverJobs;
+use crate::sokoban::PlayState;
+
+/// Shows the move and push counts and the LURD recording of the level in
+/// the corner of the screen while the `debug` action is toggled on. The
+/// `copy` action then puts the recording on the clipboard. The progress of
+/// any running solver jobs is shown below.
+#[derive(Default)]
+pub struct DebugSystem {
+    text: Option<Entity>,
+    debug_held: bool,
+    copy_held: bool,
+}
+
+impl<'s> System<'s> for DebugSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, UiTransform>,
+        WriteStorage<'s, UiText>,
+        Read<'s, InputHandler<String, String>>,
+        Read<'s, PlayState>,
+        Read<'s, SolverJobs>,
+        ReadExpect<'s, Loader>,
+        Read<'s, AssetStorage<FontAsset>>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, mut ui_transforms, mut ui_texts, input, state, jobs, loader, fonts): Self::SystemData,
+    ) {
+        let debug = input.action_is_down("debug").unwrap_or(false);
+        let copy = input.action_is_down("copy").unwrap_or(false);
+        let debug_pressed = debug && !self.debug_held;
+        let copy_pressed = copy && !self.copy_held;
+        self.debug_held = debug;
+        self.copy_held = copy;
+
+        if debug_pressed {
+            match self.text.take() {
+                Some(text) => {
+                    if let Err(err) = entities.delete(text) {
+                        error!("Failed to delete the debug view: {:?}", err);
+                    }
+                }
+                None => {
+                    let mut ui_text = UiText::new(
+                        get_default_font(&loader, &fonts),
+                        String::new(),
+                        [1.0, 1.0, 1.0, 1.0],
+                        20.0,
+                    );
+                    ui_text.line_mode = LineMode::Wrap;
+                    ui_text.align = Anchor::TopLeft;
+
+                    self.text = Some(
+                        entities
+                            .build_entity()
+                            .with(
+                                UiTransform::new(
+                                    "debug".to_string(),
+                                    Anchor::TopLeft,
+                                    410.0,
+                                    -210.0,
+                                    1.0,
+                                    800.0,
+                                    400.0,
+                                    0,
+                                ),
+                                &mut ui_transforms,
+                            )
+                            .with(ui_text, &mut ui_texts)
+                            .build(),
+                    );
+                }
+            }
+        }
+
+        let text = match self.text {
+            Some(text) => text,
+            None => return,
+        };
+
+        let (moves, pushes) = state
+            .game
+            .as_ref()
+            .map_or((0, 0), |game| (game.moves(), game.pushes()));
+        let lurd = to_lurd(&state.history.steps());
+
+        if copy_pressed {
+            let copied = ClipboardContext::new()
+                .and_then(|mut clipboard| clipboard.set_contents(lurd.clone()));
+
+            match copied {
+                Ok(()) => info!("Copied {} to the clipboard", lurd),
+                Err(err) => error!("Failed to copy to the clipboard: {}", err),
+            }
+        }
+
+        let mut status = format!("Moves: {}  Pushes: {}\n{}", moves, pushes, lurd);
+        for id in jobs.running() {
+            if let Some(progress) = jobs.progress(id) {
+                status.push_str(&format!(
+                    "\nSolving: {} nodes, {} pushes deep, {:.1}s",
+                    progress.nodes,
+                    progress.depth,
+                    progress.elapsed.as_secs_f32()
+                ));
+            }
+        }
+
+        if let Some(ui_text) = ui_texts.get_mut(text) {
+            ui_text.text = status;
+        }
+    }
+}
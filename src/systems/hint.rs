@@ -0,0 +1,261 @@
+use std::time::Duration;
+
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    core::Transform,
+    ecs::{Entities, Entity, Read, ReadExpect, Resources, System, SystemData, Write, WriteStorage},
+    input::InputHandler,
+    renderer::{SpriteRender, SpriteSheetHandle, Transparent},
+    shrev::{EventChannel, ReaderId},
+    ui::{get_default_font, Anchor, FontAsset, UiText, UiTransform},
+};
+use log::{error, info};
+
+use amethyst_sokoban::game::{Direction, GameState};
+use amethyst_sokoban::lurd::char_to_step;
+use amethyst_sokoban::solver::{Limits, Metric, Outcome};
+
+use crate::jobs::{JobId, SolverDone, SolverJobs};
+use crate::replay::Replay;
+use crate::sokoban::{PlayState, TILE_SIZE};
+
+/// The sprites drawn by `HintSystem`: a frame around the box to push,
+/// followed by arrows pointing up, down, left and right.
+pub struct HintSpriteSheet(pub SpriteSheetHandle);
+
+/// How long to look for a hint before giving up.
+const HINT_TIME: Duration = Duration::from_secs(5);
+
+/// The player, the boxes and the level they are in, to tell whether a hint
+/// still applies.
+type Position = (usize, (usize, usize), Vec<(usize, usize)>);
+
+fn position(index: usize, game: &GameState) -> Position {
+    let mut boxes: Vec<(usize, usize)> = game.boxes().iter().cloned().collect();
+    boxes.sort();
+
+    (index, game.player(), boxes)
+}
+
+/// The box to push next and which way, going by a solution from where the
+/// player stands.
+fn first_push(game: &GameState, lurd: &str) -> Option<((usize, usize), Direction)> {
+    let mut player = game.player();
+
+    for step in lurd.chars().filter_map(char_to_step) {
+        let next = step.direction.apply(player)?;
+        if step.push {
+            return Some((next, step.direction));
+        }
+        player = next;
+    }
+
+    None
+}
+
+fn arrow_sprite(direction: Direction) -> usize {
+    match direction {
+        Direction::Up => 1,
+        Direction::Down => 2,
+        Direction::Left => 3,
+        Direction::Right => 4,
+    }
+}
+
+fn direction_name(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Up => "up",
+        Direction::Down => "down",
+        Direction::Left => "left",
+        Direction::Right => "right",
+    }
+}
+
+/// Solves the level from where the player stands on the `hint` action, then
+/// highlights the next box to push and the way to push it. The hint goes
+/// away as soon as the player, or a box, moves.
+#[derive(Default)]
+pub struct HintSystem {
+    held: bool,
+    done_reader: Option<ReaderId<SolverDone>>,
+    /// The solver job looking for the hint, while it runs.
+    job: Option<JobId>,
+    /// The position the hint is for.
+    position: Option<Position>,
+    /// The sprites and text showing the hint.
+    shown: Vec<Entity>,
+}
+
+impl HintSystem {
+    fn clear(&mut self, entities: &Entities<'_>) {
+        for entity in self.shown.drain(..) {
+            if let Err(err) = entities.delete(entity) {
+                error!("Failed to delete the hint: {:?}", err);
+            }
+        }
+    }
+}
+
+impl<'s> System<'s> for HintSystem {
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, SpriteRender>,
+        WriteStorage<'s, Transparent>,
+        WriteStorage<'s, UiTransform>,
+        WriteStorage<'s, UiText>,
+        Read<'s, InputHandler<String, String>>,
+        Read<'s, PlayState>,
+        Read<'s, Replay>,
+        Write<'s, SolverJobs>,
+        Read<'s, EventChannel<SolverDone>>,
+        ReadExpect<'s, HintSpriteSheet>,
+        ReadExpect<'s, Loader>,
+        Read<'s, AssetStorage<FontAsset>>,
+    );
+
+    fn setup(&mut self, res: &mut Resources) {
+        Self::SystemData::setup(res);
+        self.done_reader = Some(
+            res.fetch_mut::<EventChannel<SolverDone>>()
+                .register_reader(),
+        );
+    }
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut transforms,
+            mut sprite_renders,
+            mut transparents,
+            mut ui_transforms,
+            mut ui_texts,
+            input,
+            state,
+            replay,
+            mut jobs,
+            done_events,
+            sprite_sheet,
+            loader,
+            fonts,
+        ): Self::SystemData,
+    ) {
+        let hint = input.action_is_down("hint").unwrap_or(false);
+        let hint_pressed = hint && !self.held;
+        self.held = hint;
+
+        let current = state.game.as_ref().map(|game| position(state.index, game));
+
+        // Whatever the hint was for, it no longer applies.
+        if self.position.is_some() && self.position != current {
+            if let Some(job) = self.job.take() {
+                jobs.cancel(job);
+            }
+            self.position = None;
+            self.clear(&entities);
+        }
+
+        let mut message = None;
+
+        if hint_pressed && !replay.is_active() && self.job.is_none() {
+            if let Some(game) = state.game.as_ref() {
+                info!("Looking for a hint");
+                self.clear(&entities);
+
+                let limits = Limits {
+                    max_time: Some(HINT_TIME),
+                    ..Limits::default()
+                };
+                self.job = Some(jobs.start(game.to_level(), Metric::Pushes, limits));
+                self.position = current.clone();
+                message = Some("Looking for a hint...".to_string());
+            }
+        }
+
+        let reader = self.done_reader.as_mut().expect("setup was not called");
+        let outcome = done_events
+            .read(reader)
+            .find(|done| Some(done.id) == self.job)
+            .map(|done| done.outcome.clone());
+
+        if let (Some(outcome), Some(game)) = (outcome, state.game.as_ref()) {
+            self.job = None;
+            self.clear(&entities);
+
+            message = Some(match outcome {
+                Outcome::Solved { lurd, .. } => match first_push(game, &lurd) {
+                    Some((box_pos, direction)) => {
+                        let mut overlays = vec![(box_pos, 0)];
+                        if let Some(to) = direction.apply(box_pos) {
+                            overlays.push((to, arrow_sprite(direction)));
+                        }
+
+                        for ((x, y), sprite_number) in overlays {
+                            let mut transform = Transform::default();
+                            transform.set_xyz(x as f32 * TILE_SIZE, y as f32 * TILE_SIZE, -10.0);
+
+                            self.shown.push(
+                                entities
+                                    .build_entity()
+                                    .with(transform, &mut transforms)
+                                    .with(
+                                        SpriteRender {
+                                            sprite_sheet: sprite_sheet.0.clone(),
+                                            sprite_number,
+                                        },
+                                        &mut sprite_renders,
+                                    )
+                                    .with(Transparent, &mut transparents)
+                                    .build(),
+                            );
+                        }
+
+                        format!("Hint: push this box {}", direction_name(direction))
+                    }
+                    None => "Nothing left to push".to_string(),
+                },
+                Outcome::Unsolvable => {
+                    "This position can't be solved. Undo some moves or restart.".to_string()
+                }
+                Outcome::GaveUp => "Couldn't find a hint in time".to_string(),
+                Outcome::Cancelled => {
+                    self.position = None;
+                    return;
+                }
+            });
+        }
+
+        if let Some(message) = message {
+            info!("{}", message);
+
+            self.shown.push(
+                entities
+                    .build_entity()
+                    .with(
+                        UiTransform::new(
+                            "hint".to_string(),
+                            Anchor::TopMiddle,
+                            0.0,
+                            -30.0,
+                            1.0,
+                            1000.0,
+                            40.0,
+                            0,
+                        ),
+                        &mut ui_transforms,
+                    )
+                    .with(
+                        UiText::new(
+                            get_default_font(&loader, &fonts),
+                            message,
+                            [1.0, 1.0, 1.0, 1.0],
+                            30.0,
+                        ),
+                        &mut ui_texts,
+                    )
+                    .build(),
+            );
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+
+use amethyst::{
+    core::Transform,
+    ecs::{Join, Read, ReadStorage, System, Write, WriteStorage},
+    input::InputHandler,
+    renderer::SpriteRender,
+};
+
+use amethyst_sokoban::game::Direction;
+use amethyst_sokoban::history::Entry;
+
+use crate::replay::Replay;
+use crate::sokoban::{Box, Movable, PlayState, Player, TILE_SIZE};
+use crate::theme::Theme;
+
+use super::grid_position;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Action {
+    Undo,
+    UndoPush,
+    Redo,
+    Restart,
+}
+
+/// Steps backward and forward through the history of the level, and
+/// restarts it, snapping the player and boxes to where they belong.
+#[derive(Default)]
+pub struct HistorySystem {
+    /// The action held down during the last frame, which is only acted on
+    /// once until it is released.
+    held: Option<Action>,
+}
+
+impl<'s> System<'s> for HistorySystem {
+    type SystemData = (
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, Movable>,
+        WriteStorage<'s, SpriteRender>,
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Box>,
+        Read<'s, InputHandler<String, String>>,
+        Read<'s, Theme>,
+        Write<'s, PlayState>,
+        Write<'s, Replay>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            mut transforms,
+            mut movables,
+            mut sprite_renders,
+            players,
+            boxes,
+            input,
+            theme,
+            mut state,
+            mut replay,
+        ): Self::SystemData,
+    ) {
+        let moving = movables.join().any(|movable| movable.moving_to.is_some());
+
+        let action = if input.action_is_down("undo_push").unwrap_or(false) {
+            Some(Action::UndoPush)
+        } else if input.action_is_down("undo").unwrap_or(false) {
+            Some(Action::Undo)
+        } else if input.action_is_down("redo").unwrap_or(false) {
+            Some(Action::Redo)
+        } else if input.action_is_down("restart").unwrap_or(false) {
+            Some(Action::Restart)
+        } else {
+            None
+        };
+
+        let pressed = action.filter(|&action| self.held != Some(action));
+        self.held = action;
+
+        // A replay steps back on its own, and ignores the keys meanwhile.
+        let pressed = if replay.is_active() {
+            if !moving && replay.retreat() {
+                Some(Action::Undo)
+            } else {
+                None
+            }
+        } else {
+            pressed
+        };
+
+        let action = match pressed {
+            Some(action) => action,
+            None => return,
+        };
+
+        let PlayState { game, history, .. } = &mut *state;
+        let game = match game.as_mut() {
+            Some(game) => game,
+            None => return,
+        };
+
+        // Leave finished levels alone, and steps that are still being
+        // animated unless the level is being restarted.
+        if (game.is_solved() && !replay.is_active()) || (action != Action::Restart && moving) {
+            return;
+        }
+
+        let entries = match action {
+            Action::Undo => history.undo().into_iter().collect(),
+            Action::UndoPush => history.undo_push(),
+            Action::Redo => history.redo().into_iter().collect(),
+            Action::Restart => {
+                history.record(Entry::Restart);
+                vec![Entry::Restart]
+            }
+        };
+
+        if entries.is_empty() {
+            return;
+        }
+
+        let undoing = action == Action::Undo || action == Action::UndoPush;
+        let mut facing = Direction::Down;
+
+        for entry in entries {
+            match entry {
+                Entry::Step(step) if undoing => {
+                    game.undo(step.direction, step.push);
+                    facing = step.direction;
+                }
+                Entry::Step(step) => {
+                    game.try_move(step.direction);
+                    facing = step.direction;
+                }
+                Entry::Restart => {
+                    game.restart();
+                    facing = Direction::Down;
+
+                    // The restart is no longer in the history, so replay
+                    // what came before it.
+                    if undoing {
+                        for step in history.steps() {
+                            game.try_move(step.direction);
+                            facing = step.direction;
+                        }
+                    }
+                }
+            }
+        }
+
+        for (_, transform, sprite_render) in (&players, &mut transforms, &mut sprite_renders).join()
+        {
+            snap(transform, game.player());
+            sprite_render.sprite_number = facing.sprite_base();
+        }
+
+        // Boxes all look alike, so those that aren't where a box should be
+        // are simply moved to wherever one is missing. Those that are may
+        // still be partway through a step when restarting, so they are
+        // snapped too.
+        let mut placed = HashSet::new();
+        let mut misplaced = Vec::new();
+
+        for (_, transform, sprite_render) in (&boxes, &mut transforms, &mut sprite_renders).join() {
+            let pos = grid_position(transform);
+
+            if game.is_box(pos) && placed.insert(pos) {
+                snap(transform, pos);
+                sprite_render.sprite_number = theme.box_sprite_for(game.is_goal(pos));
+            } else {
+                misplaced.push((transform, sprite_render));
+            }
+        }
+
+        for ((transform, sprite_render), &pos) in
+            misplaced.into_iter().zip(game.boxes().difference(&placed))
+        {
+            snap(transform, pos);
+            sprite_render.sprite_number = theme.box_sprite_for(game.is_goal(pos));
+        }
+
+        // Everything is where it belongs now, so nothing is left to animate.
+        for movable in (&mut movables).join() {
+            movable.moving_to = None;
+        }
+    }
+}
+
+fn snap(transform: &mut Transform, (x, y): (usize, usize)) {
+    transform.set_x(x as f32 * TILE_SIZE);
+    transform.set_y(y as f32 * TILE_SIZE);
+}
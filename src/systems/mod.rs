@@ -1,5 +1,27 @@
+use amethyst::core::Transform;
+
+use crate::sokoban::TILE_SIZE;
+
+mod deadlock;
+mod debug;
+mod hint;
+mod history;
 mod r#move;
 mod player;
+mod solver;
 
+pub use self::deadlock::DeadlockSystem;
+pub use self::debug::DebugSystem;
+pub use self::hint::{HintSpriteSheet, HintSystem};
+pub use self::history::HistorySystem;
 pub use self::player::PlayerSystem;
 pub use self::r#move::MoveSystem;
+pub use self::solver::SolverSystem;
+
+/// The tile an entity stands on, going by its transform.
+fn grid_position(transform: &Transform) -> (usize, usize) {
+    let x = (transform.translation().x / TILE_SIZE).round() as usize;
+    let y = (transform.translation().y / TILE_SIZE).round() as usize;
+
+    (x, y)
+}
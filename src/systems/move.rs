@@ -1,10 +1,11 @@
 use amethyst::{
     core::{Time, Transform},
-    ecs::{Join, Read, ReadStorage, System, WriteStorage},
-    input::InputHandler,
+    ecs::{Join, Read, System, WriteStorage},
 };
 
-use crate::sokoban::{Direction, Movable};
+use amethyst_sokoban::game::Direction;
+
+use crate::sokoban::{Movable, TILE_SIZE};
 
 pub struct MoveSystem;
 
@@ -19,7 +20,7 @@ impl<'s> System<'s> for MoveSystem {
         for (movable, transform) in (&mut movables, &mut transforms).join() {
             if let Some((tx, ty, direction)) = movable.moving_to {
                 let (dx, dy) = direction.to_velocity(48.0 * time.delta_seconds());
-                let target = (tx as f32 * 16.0, ty as f32 * 16.0);
+                let target = (tx as f32 * TILE_SIZE, ty as f32 * TILE_SIZE);
 
                 transform.translate_x(dx);
                 transform.translate_y(dy);
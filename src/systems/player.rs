@@ -1,33 +1,87 @@
 use amethyst::{
     core::{Time, Transform},
-    ecs::{Join, Read, ReadStorage, System, WriteStorage},
+    ecs::{Entities, Entity, Join, Read, ReadStorage, System, Write, WriteStorage},
     input::InputHandler,
     renderer::SpriteRender,
+    shrev::EventChannel,
 };
 
-use crate::sokoban::{Box, Direction, Movable, PlayState, Player};
+use amethyst_sokoban::game::{Direction, Move};
+use amethyst_sokoban::history::{Entry, Step};
+use amethyst_sokoban::lurd::to_lurd;
 
-pub struct PlayerSystem;
+use crate::replay::Replay;
+use crate::sokoban::{Box, Deadlock, LevelComplete, Movable, PlayState, Player};
+use crate::theme::{GameConfig, Theme};
+
+use super::grid_position;
+
+#[derive(Default)]
+pub struct PlayerSystem {
+    /// A push that was taken back for causing a deadlock, and where the
+    /// player stood, so that holding the key down doesn't try it again.
+    refused: Option<(Direction, (usize, usize))>,
+}
 
 impl<'s> System<'s> for PlayerSystem {
     type SystemData = (
-        WriteStorage<'s, Transform>,
+        Entities<'s>,
+        ReadStorage<'s, Transform>,
         WriteStorage<'s, Movable>,
         WriteStorage<'s, SpriteRender>,
         ReadStorage<'s, Player>,
         ReadStorage<'s, Box>,
         Read<'s, InputHandler<String, String>>,
         Read<'s, Time>,
-        Read<'s, PlayState>,
+        Read<'s, Theme>,
+        Read<'s, GameConfig>,
+        Write<'s, PlayState>,
+        Write<'s, EventChannel<LevelComplete>>,
+        Write<'s, EventChannel<Deadlock>>,
+        Write<'s, Replay>,
     );
 
     fn run(
         &mut self,
-        (mut transforms, mut movables, mut sprite_renders, players, boxes, input, time, state): Self::SystemData,
+        (
+            entities,
+            transforms,
+            mut movables,
+            mut sprite_renders,
+            players,
+            boxes,
+            input,
+            time,
+            theme,
+            config,
+            mut state,
+            mut complete_events,
+            mut deadlock_events,
+            mut replay,
+        ): Self::SystemData,
     ) {
-        for (player, movable, transform, sprite_render) in
-            (&players, &mut movables, &transforms, &mut sprite_renders).join()
-        {
+        let PlayState {
+            index,
+            game,
+            history,
+            deadlocks,
+            ..
+        } = &mut *state;
+        let game = match game.as_mut() {
+            Some(game) => game,
+            None => return,
+        };
+
+        let get_box_at = |pos: (usize, usize)| -> Option<Entity> {
+            (&entities, &transforms, &boxes)
+                .join()
+                .find(|(_, transform, _)| grid_position(transform) == pos)
+                .map(|(entity, _, _)| entity)
+        };
+
+        let mut pushes = Vec::new();
+
+        for (_, movable, sprite_render) in (&players, &mut movables, &mut sprite_renders).join() {
             if movable.moving_to.is_some() {
                 let base = ((sprite_render.sprite_number as f32) / 4.0) as usize;
                 let offset = ((time.frame_number() as f32) / 8.0) as usize;
@@ -39,86 +93,98 @@ impl<'s> System<'s> for PlayerSystem {
                 sprite_render.sprite_number = base * 4;
             }
 
-            let get_box_at = |x: usize, y: usize| -> Option<&Box> {
-                for (transform, r#box) in (&transforms, &boxes).join() {
-                    let cx = (transform.translation().x / 16.0) as usize;
-                    let cy = (transform.translation().y / 16.0) as usize;
+            // The level is over, so leave the player be until the next one.
+            if game.is_solved() {
+                continue;
+            }
 
-                    if cx == x && cy == y {
-                        return Some(r#box);
-                    }
+            let direction = if replay.is_active() {
+                match replay.advance() {
+                    Some(step) => step.direction,
+                    None => continue,
                 }
-
-                None
+            } else if input.action_is_down("up").unwrap() {
+                Direction::Up
+            } else if input.action_is_down("down").unwrap() {
+                Direction::Down
+            } else if input.action_is_down("left").unwrap() {
+                Direction::Left
+            } else if input.action_is_down("right").unwrap() {
+                Direction::Right
+            } else {
+                continue;
             };
 
-            let level = state.level.as_ref().unwrap();
-
-            let x = (transform.translation().x / 16.0) as usize;
-            let y = (transform.translation().y / 16.0) as usize;
+            if !replay.is_active() && self.refused == Some((direction, game.player())) {
+                continue;
+            }
+            self.refused = None;
 
-            if input.action_is_down("up").unwrap() {
-                if !level.is_wall(x, y + 1) {
-                    if let Some(r#box) = get_box_at(x, y + 1) {
-                        if !level.is_wall(x, y + 2) {
-                            if get_box_at(x, y + 2).is_none() {
-                                movable.moving_to = Some((x, y + 1, Direction::Up));
-                                sprite_render.sprite_number = 8;
-                            }
-                        }
-                    } else {
-                        movable.moving_to = Some((x, y + 1, Direction::Up));
-                        sprite_render.sprite_number = 8;
-                    }
+            let to = match game.try_move(direction) {
+                Move::Walk { to } => {
+                    history.record(Entry::Step(Step {
+                        direction,
+                        push: false,
+                    }));
+                    to
                 }
-            }
+                Move::Push { to, box_to } => {
+                    let deadlock = if replay.is_active() {
+                        None
+                    } else {
+                        deadlocks.check(game, box_to)
+                    };
+
+                    if let Some(kind) = deadlock {
+                        let undone = config.auto_undo_deadlocks;
+                        deadlock_events.single_write(Deadlock {
+                            index: *index,
+                            kind,
+                            undone,
+                        });
 
-            if input.action_is_down("down").unwrap() {
-                if !level.is_wall(x, y - 1) {
-                    if let Some(r#box) = get_box_at(x, y - 1) {
-                        if !level.is_wall(x, y - 2) {
-                            if get_box_at(x, y - 2).is_none() {
-                                movable.moving_to = Some((x, y - 1, Direction::Down));
-                                sprite_render.sprite_number = 0;
-                            }
+                        // The push is only recorded once it stands, so
+                        // taking it back leaves the history as it was.
+                        if undone {
+                            game.undo(direction, true);
+                            self.refused = Some((direction, game.player()));
+                            continue;
                         }
-                    } else {
-                        movable.moving_to = Some((x, y - 1, Direction::Down));
-                        sprite_render.sprite_number = 0;
                     }
-                }
-            }
 
-            if input.action_is_down("left").unwrap() {
-                if !level.is_wall(x - 1, y) {
-                    if let Some(r#box) = get_box_at(x - 1, y) {
-                        if !level.is_wall(x - 2, y) {
-                            if get_box_at(x - 2, y).is_none() {
-                                movable.moving_to = Some((x - 1, y, Direction::Left));
-                                sprite_render.sprite_number = 12;
-                            }
-                        }
-                    } else {
-                        movable.moving_to = Some((x - 1, y, Direction::Left));
-                        sprite_render.sprite_number = 12;
+                    history.record(Entry::Step(Step {
+                        direction,
+                        push: true,
+                    }));
+
+                    if let Some(r#box) = get_box_at(to) {
+                        pushes.push((r#box, box_to, direction));
                     }
+                    to
                 }
+                Move::Blocked => continue,
+            };
+
+            movable.moving_to = Some((to.0, to.1, direction));
+            sprite_render.sprite_number = direction.sprite_base();
+        }
+
+        if !pushes.is_empty() && game.is_solved() && !replay.is_active() {
+            complete_events.single_write(LevelComplete {
+                index: *index,
+                moves: game.moves(),
+                pushes: game.pushes(),
+                solution: to_lurd(&history.steps()),
+            });
+        }
+
+        for (r#box, box_to, direction) in pushes {
+            if let Some(movable) = movables.get_mut(r#box) {
+                movable.moving_to = Some((box_to.0, box_to.1, direction));
             }
 
-            if input.action_is_down("right").unwrap() {
-                if !level.is_wall(x + 1, y) {
-                    if let Some(r#box) = get_box_at(x + 1, y) {
-                        if !level.is_wall(x + 2, y) {
-                            if get_box_at(x + 2, y).is_none() {
-                                movable.moving_to = Some((x + 1, y, Direction::Right));
-                                sprite_render.sprite_number = 4;
-                            }
-                        }
-                    } else {
-                        movable.moving_to = Some((x + 1, y, Direction::Right));
-                        sprite_render.sprite_number = 4;
-                    }
-                }
+            if let Some(sprite_render) = sprite_renders.get_mut(r#box) {
+                sprite_render.sprite_number = theme.box_sprite_for(game.is_goal(box_to));
             }
         }
     }
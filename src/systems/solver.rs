@@ -0,0 +1,86 @@
+use amethyst::{
+    ecs::{Read, System, Write},
+    input::InputHandler,
+    shrev::EventChannel,
+};
+use log::info;
+
+use amethyst_sokoban::solver::{Limits, Metric, Outcome};
+
+use crate::jobs::{JobId, SolverDone, SolverJobs};
+use crate::sokoban::PlayState;
+
+/// Hands the results of finished solver jobs to the ECS as `SolverDone`
+/// events. The `solve` action starts solving the current level for the
+/// fewest pushes, to compare with, and `cancel` stops every running job.
+#[derive(Default)]
+pub struct SolverSystem {
+    solve_held: bool,
+    cancel_held: bool,
+    /// The job started by the `solve` action, if it is still running.
+    optimum: Option<(JobId, usize)>,
+}
+
+impl<'s> System<'s> for SolverSystem {
+    type SystemData = (
+        Write<'s, SolverJobs>,
+        Write<'s, EventChannel<SolverDone>>,
+        Read<'s, InputHandler<String, String>>,
+        Read<'s, PlayState>,
+    );
+
+    fn run(&mut self, (mut jobs, mut done_channel, input, state): Self::SystemData) {
+        let solve = input.action_is_down("solve").unwrap_or(false);
+        let cancel = input.action_is_down("cancel").unwrap_or(false);
+        let solve_pressed = solve && !self.solve_held;
+        let cancel_pressed = cancel && !self.cancel_held;
+        self.solve_held = solve;
+        self.cancel_held = cancel;
+
+        if solve_pressed && self.optimum.is_none() {
+            if let Some(level) = state.level.clone() {
+                info!("Solving level {}", state.index + 1);
+                let id = jobs.start(level, Metric::Pushes, Limits::default());
+                self.optimum = Some((id, state.index));
+            }
+        }
+
+        if cancel_pressed && !jobs.is_empty() {
+            info!("Cancelling the solver");
+            jobs.cancel_all();
+        }
+
+        let finished = jobs.take_finished();
+
+        for done in &finished {
+            match self.optimum {
+                Some((id, index)) if id == done.id => {
+                    self.optimum = None;
+                    report_optimum(index, &done.outcome);
+                }
+                _ => {}
+            }
+        }
+
+        done_channel.iter_write(finished);
+    }
+}
+
+fn report_optimum(index: usize, outcome: &Outcome) {
+    match outcome {
+        Outcome::Solved {
+            lurd,
+            moves,
+            pushes,
+        } => info!(
+            "Level {} can be solved in {} moves and {} pushes: {}",
+            index + 1,
+            moves,
+            pushes,
+            lurd
+        ),
+        Outcome::Unsolvable => info!("Level {} can't be solved", index + 1),
+        Outcome::GaveUp => info!("Gave up solving level {}", index + 1),
+        Outcome::Cancelled => info!("Stopped solving level {}", index + 1),
+    }
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+/// How levels look: the spritesheet to draw them with, which of its sprites
+/// to use for boxes and goals, and how to lay out ground and walls.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Theme {
+    /// Name of the `texture/<name>_spritesheet.png` and `.ron` pair.
+    pub spritesheet: String,
+    pub box_sprite: usize,
+    /// The sprite for a box resting on a goal.
+    pub box_on_goal_sprite: usize,
+    pub goal_sprite: usize,
+    /// File in `texture/` holding the ground autotile rules.
+    pub ground: String,
+    /// File in `texture/` holding the wall autotile rules.
+    pub walls: String,
+    /// The color around the level, as RGBA.
+    pub clear_color: [f32; 4],
+}
+
+impl Theme {
+    /// The sprite for a box, depending on whether it rests on a goal.
+    pub fn box_sprite_for(&self, on_goal: bool) -> usize {
+        if on_goal {
+            self.box_on_goal_sprite
+        } else {
+            self.box_sprite
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            spritesheet: "outdoor".to_string(),
+            box_sprite: 31,
+            box_on_goal_sprite: 38,
+            goal_sprite: 32,
+            ground: "outdoor_autotile.ron".to_string(),
+            walls: "outdoor_walls.ron".to_string(),
+            clear_color: [0.00196, 0.23726, 0.21765, 1.0],
+        }
+    }
+}
+
+/// Settings read from `resources/game_config.ron`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    /// The theme for levels that don't pick one with a `Theme:` line.
+    pub theme: String,
+    /// Whether to darken the tiles a box can never be pushed from onto a
+    /// goal.
+    pub shade_dead_squares: bool,
+    /// Whether to take back a push as soon as it causes a deadlock.
+    pub auto_undo_deadlocks: bool,
+}
+
+impl Default for GameConfig {
+    fn default() -> GameConfig {
+        GameConfig {
+            theme: "outdoor".to_string(),
+            shade_dead_squares: false,
+            auto_undo_deadlocks: false,
+        }
+    }
+}
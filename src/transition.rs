@@ -0,0 +1,155 @@
+use std::path::Path;
+
+use amethyst::{
+    assets::{AssetStorage, Loader},
+    core::Time,
+    ecs::prelude::*,
+    prelude::*,
+    renderer::SpriteSheetHandle,
+    ui::{get_default_font, Anchor, FontAsset, UiText, UiTransform},
+};
+use log::{error, info};
+
+use amethyst_sokoban::collection::LevelCollection;
+use amethyst_sokoban::solution::{Solution, Solutions};
+
+use crate::sokoban::{load_level, unload_level, LevelComplete};
+
+/// How long the result is shown before the next level starts, in seconds.
+const DELAY: f32 = 2.0;
+
+/// Shows that a level was solved, then swaps it for the next level of the
+/// collection and hands back to the `Sokoban` state. After the last level,
+/// the result stays up.
+pub struct Transition {
+    completed: LevelComplete,
+    character_handle: SpriteSheetHandle,
+    remaining: f32,
+    has_next: bool,
+    text: Option<Entity>,
+}
+
+impl Transition {
+    pub fn new(completed: LevelComplete, character_handle: SpriteSheetHandle) -> Transition {
+        Transition {
+            completed,
+            character_handle,
+            remaining: DELAY,
+            has_next: false,
+            text: None,
+        }
+    }
+}
+
+impl SimpleState for Transition {
+    fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        let world = data.world;
+
+        let message = {
+            let collection = world.read_resource::<LevelCollection>();
+            let index = self.completed.index;
+            let level_title = collection
+                .get(index)
+                .and_then(|entry| entry.metadata.title.clone());
+
+            if let Some(path) = collection.path() {
+                save_solution(
+                    &Solutions::path_for(path),
+                    index + 1,
+                    Solution {
+                        title: level_title.clone(),
+                        lurd: self.completed.solution.clone(),
+                    },
+                );
+            }
+
+            let title = level_title.unwrap_or_else(|| format!("Level {}", index + 1));
+
+            self.has_next = index + 1 < collection.len();
+
+            let result = format!(
+                "{} solved in {} moves and {} pushes",
+                title, self.completed.moves, self.completed.pushes
+            );
+
+            if self.has_next {
+                format!("{}!", result)
+            } else {
+                format!("{}, that was the last level!", result)
+            }
+        };
+
+        info!("{}", message);
+
+        let font = get_default_font(
+            &world.read_resource::<Loader>(),
+            &world.read_resource::<AssetStorage<FontAsset>>(),
+        );
+
+        self.text = Some(
+            world
+                .create_entity()
+                .with(UiTransform::new(
+                    "level_complete".to_string(),
+                    Anchor::Middle,
+                    0.0,
+                    0.0,
+                    1.0,
+                    1000.0,
+                    50.0,
+                    0,
+                ))
+                .with(UiText::new(font, message, [1.0, 1.0, 1.0, 1.0], 40.0))
+                .build(),
+        );
+    }
+
+    fn on_stop(&mut self, data: StateData<'_, GameData<'_, '_>>) {
+        if let Some(text) = self.text.take() {
+            if let Err(err) = data.world.delete_entity(text) {
+                error!("Failed to delete the result text: {:?}", err);
+            }
+        }
+    }
+
+    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
+        if !self.has_next {
+            return Trans::None;
+        }
+
+        self.remaining -= data.world.read_resource::<Time>().delta_seconds();
+        if self.remaining > 0.0 {
+            return Trans::None;
+        }
+
+        unload_level(data.world);
+        load_level(
+            data.world,
+            self.character_handle.clone(),
+            self.completed.index + 1,
+        );
+
+        Trans::Pop
+    }
+}
+
+/// Adds a solution to the solutions file, unless a better one is already
+/// in there.
+fn save_solution(path: &Path, number: usize, solution: Solution) {
+    let mut solutions = match Solutions::load(path) {
+        Ok(solutions) => solutions,
+        Err(err) => {
+            error!("Failed to load {}: {}", path.display(), err);
+            return;
+        }
+    };
+
+    if !solutions.insert(number, solution) {
+        return;
+    }
+
+    match solutions.save(path) {
+        Ok(()) => info!("Saved the solution to {}", path.display()),
+        Err(err) => error!("Failed to save {}: {}", path.display(), err),
+    }
+}